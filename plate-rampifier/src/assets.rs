@@ -0,0 +1,284 @@
+use std::collections::{HashMap, HashSet};
+use std::{fs, path::Path};
+use brickadia::{
+    save::{Brick, Size},
+    util::get_scale_axis,
+};
+use rampifier::derampify::RampShape;
+
+// How a brick fills the voxels inside its bounds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FillRule {
+    // Every voxel inside the bounds is solid.
+    Box,
+
+    // Only voxels whose centers lie inside the inscribed circle are solid, for round bricks.
+    Cylinder,
+
+    // Voxels under the slope of a ramp or wedge are solid.
+    Ramp(RampShape),
+}
+
+impl FillRule {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "box" => Some(FillRule::Box),
+            "cylinder" | "round" => Some(FillRule::Cylinder),
+            _ => None,
+        }
+    }
+}
+
+// Shape of a brick asset, sized like `Size::Procedural` (half extents in units).
+#[derive(Copy, Clone, Debug)]
+pub struct AssetShape {
+    pub size: (u32, u32, u32),
+    pub fill: FillRule,
+}
+
+impl AssetShape {
+    const fn new(size: (u32, u32, u32), fill: FillRule) -> Self {
+        Self { size, fill }
+    }
+}
+
+// Fixed-size assets that are plate aligned and (mostly) solid. These are saved
+// with `Size::Empty`, so their size can only be known from their name.
+const BUILTIN_ASSETS: [(&str, AssetShape); 18] = [
+    ("B_1x1_Brick", AssetShape::new((5, 5, 6), FillRule::Box)),
+    ("B_1x1_Brick_Side", AssetShape::new((5, 5, 6), FillRule::Box)),
+    ("B_1x4_Brick_Side", AssetShape::new((20, 5, 6), FillRule::Box)),
+    ("B_2x4_Plate", AssetShape::new((20, 10, 2), FillRule::Box)),
+    ("B_1x2f_Plate_Center", AssetShape::new((10, 5, 2), FillRule::Box)),
+    ("B_1x2f_Plate_Center_Inv", AssetShape::new((10, 5, 2), FillRule::Box)),
+    ("B_2x2f_Plate_Center", AssetShape::new((10, 10, 2), FillRule::Box)),
+    ("B_2x2f_Plate_Center_Inv", AssetShape::new((10, 10, 2), FillRule::Box)),
+    ("B_Hedge_1x1", AssetShape::new((5, 5, 6), FillRule::Box)),
+    ("B_Hedge_1x2", AssetShape::new((5, 10, 6), FillRule::Box)),
+    ("B_Hedge_1x4", AssetShape::new((5, 20, 6), FillRule::Box)),
+    ("B_1x1_Round", AssetShape::new((5, 5, 6), FillRule::Cylinder)),
+    ("B_1x1F_Round", AssetShape::new((5, 5, 2), FillRule::Cylinder)),
+    ("B_1x1F_Octo", AssetShape::new((5, 5, 2), FillRule::Cylinder)),
+    ("B_2x2_Round", AssetShape::new((10, 10, 6), FillRule::Cylinder)),
+    ("B_2x2F_Round", AssetShape::new((10, 10, 2), FillRule::Cylinder)),
+    ("B_2x2F_Octo", AssetShape::new((10, 10, 2), FillRule::Cylinder)),
+    ("B_4x4_Round", AssetShape::new((20, 20, 6), FillRule::Cylinder)),
+];
+
+// Procedural assets that are plain boxes. With passthrough, only these are voxelized,
+// so ramps and wedges are kept as they are. Without it, every procedural brick is
+// filled as a box, except ramps read back with `AssetTable::insert_ramp`.
+const BUILTIN_PROCEDURAL_ASSETS: [&str; 5] = [
+    "PB_DefaultBrick",
+    "PB_DefaultTile",
+    "PB_DefaultSmoothTile",
+    "PB_DefaultStudded",
+    "PB_DefaultMicroBrick",
+];
+
+// A brick's shape on the voxel grid, after applying its direction and rotation.
+#[derive(Copy, Clone, Debug)]
+pub struct VoxelShape {
+    // Size in voxels.
+    pub size: (usize, usize, usize),
+
+    pub fill: FillRule,
+
+    // World axis the brick's own Z axis points along. Cylinders are round around it.
+    pub axis: u8,
+}
+
+impl VoxelShape {
+    // Shape of a procedural ramp or wedge brick with a crest `crest` voxels long, see `RampShape`.
+    pub fn ramp(brick: &Brick, voxel_size: (u32, u32, u32), crest: usize) -> Option<Self> {
+        let ramp = RampShape::from_brick(brick, voxel_size, crest)?;
+        Some(Self { size: ramp.size, fill: FillRule::Ramp(ramp), axis: 2 })
+    }
+
+    // Is the voxel at this offset from the brick's minimum corner solid?
+    pub fn contains(&self, offset: (usize, usize, usize)) -> bool {
+        match self.fill {
+            FillRule::Box => true,
+            FillRule::Ramp(ramp) => ramp.contains(offset),
+            FillRule::Cylinder => {
+                let size = [self.size.0, self.size.1, self.size.2];
+                let offset = [offset.0, offset.1, offset.2];
+
+                // Normalized distance from the cell center to the center of the circle.
+                let mut dist = 0.0;
+                for a in 0..3 {
+                    if a != self.axis as usize {
+                        let d = (offset[a] as f32 + 0.5) / size[a] as f32 * 2.0 - 1.0;
+                        dist += d * d;
+                    }
+                }
+
+                dist <= 1.0
+            }
+        }
+    }
+}
+
+pub struct AssetTable {
+    shapes: HashMap<String, AssetShape>,
+    procedural: HashSet<String>,
+
+    // Ramp assets and the length of their crests, in voxels.
+    ramps: HashMap<String, usize>,
+
+    // Only voxelize procedural assets in `procedural`.
+    only_listed_procedural: bool,
+}
+
+impl AssetTable {
+    pub fn builtin() -> Self {
+        Self {
+            shapes: BUILTIN_ASSETS
+                .iter()
+                .map(|&(name, shape)| (name.to_string(), shape))
+                .collect(),
+            procedural: BUILTIN_PROCEDURAL_ASSETS
+                .iter()
+                .map(|&name| name.to_string())
+                .collect(),
+            ramps: HashMap::new(),
+            only_listed_procedural: false,
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, shape: AssetShape) {
+        self.shapes.insert(name.to_string(), shape);
+    }
+
+    pub fn insert_procedural(&mut self, name: &str) {
+        self.procedural.insert(name.to_string());
+    }
+
+    // Voxelize only the procedural assets in the table, for --passthrough.
+    pub fn only_listed_procedural(&mut self) {
+        self.only_listed_procedural = true;
+    }
+
+    // Procedural ramp or wedge asset, shaped like the ramps the rampifier makes with `ramp_crest` set to `crest`.
+    pub fn insert_ramp(&mut self, name: &str, crest: usize) {
+        self.ramps.insert(name.to_string(), crest);
+    }
+
+    pub fn get(&self, name: &str) -> Option<AssetShape> {
+        self.shapes.get(name).copied()
+    }
+
+    /* Reads additions to the table from a text file, one asset per line.
+     * Sizes are in studs and plates:
+     *
+     *   # name           x  y  z  fill
+     *   B_1x1_Cone       1  1  3  cylinder
+     *   B_Swirl_Plate    1  1  2
+     *   PB_MyBrick       procedural
+     *   PB_MyRamp        ramp
+     *   PB_MyCrestRamp   ramp  1
+     *
+     * The fill rule is optional and defaults to box. Entries replace
+     * built-in assets with the same name. Box shaped procedural assets
+     * take their size from the brick instead, ramps also take their slope
+     * from it, see `RampShape`. A ramp's crest is in voxels and defaults
+     * to 0, all slope like a micro wedge.
+     */
+    pub fn load_additions(&mut self, path: &Path) -> Result<usize, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}, {}", path.display(), e))?;
+        let path = path.display();
+
+        let mut count = 0;
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || format!("{}:{}: expected `name x y z [box|cylinder]`, `name procedural` or `name ramp [crest]`", path, line_number + 1);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            if parts.len() == 2 && parts[1] == "procedural" {
+                self.insert_procedural(parts[0]);
+                count += 1;
+                continue;
+            }
+
+            if (parts.len() == 2 || parts.len() == 3) && parts[1] == "ramp" {
+                let crest = match parts.get(2) {
+                    Some(crest) => crest.parse().map_err(|_| invalid())?,
+                    None => 0,
+                };
+
+                self.insert_ramp(parts[0], crest);
+                count += 1;
+                continue;
+            }
+
+            if parts.len() != 4 && parts.len() != 5 {
+                return Err(invalid());
+            }
+
+            let mut size = [0u32; 3];
+            for (i, part) in parts[1..4].iter().enumerate() {
+                size[i] = part.parse().map_err(|_| invalid())?;
+                if size[i] == 0 {
+                    return Err(invalid());
+                }
+            }
+
+            let fill = match parts.get(4) {
+                Some(name) => FillRule::parse(name).ok_or_else(invalid)?,
+                None => FillRule::Box,
+            };
+
+            self.insert(parts[0], AssetShape::new((size[0] * 5, size[1] * 5, size[2] * 2), fill));
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    // Returns the shape of a brick on a grid of `voxel_size` voxels (half extents, like `Size::Procedural`),
+    // or None if it can't be voxelized. Fixed-size bricks must be in the table, procedural ones too with `only_listed_procedural`.
+    pub fn brick_shape(&self, brick: &Brick, asset_names: &[String], voxel_size: (u32, u32, u32)) -> Option<VoxelShape> {
+        let name = asset_names.get(brick.asset_name_index as usize)?;
+
+        if let Some(&crest) = self.ramps.get(name) {
+            return VoxelShape::ramp(brick, voxel_size, crest);
+        }
+
+        let shape = match brick.size {
+            Size::Procedural(w_half, l_half, h_half) => {
+                if self.only_listed_procedural && !self.procedural.contains(name) {
+                    return None;
+                }
+                AssetShape::new((w_half, l_half, h_half), FillRule::Box)
+            }
+            Size::Empty => self.get(name)?,
+        };
+
+        let local = [shape.size.0, shape.size.1, shape.size.2];
+        let world = [
+            local[get_scale_axis(brick, 0) as usize],
+            local[get_scale_axis(brick, 1) as usize],
+            local[get_scale_axis(brick, 2) as usize],
+        ];
+
+        // Must line up with the voxel grid on every axis.
+        let (vx, vy, vz) = voxel_size;
+        if world[0] % vx != 0 || world[1] % vy != 0 || world[2] % vz != 0 {
+            return None;
+        }
+
+        let size = ((world[0] / vx) as usize, (world[1] / vy) as usize, (world[2] / vz) as usize);
+        if size.0 == 0 || size.1 == 0 || size.2 == 0 {
+            return None;
+        }
+
+        let axis = (0..3).find(|&a| get_scale_axis(brick, a) == 2).unwrap_or(2);
+
+        Some(VoxelShape { size, fill: shape.fill, axis })
+    }
+}
@@ -0,0 +1,69 @@
+use brickadia::save::{BrickOwner, Header1, Header2, SaveData, User};
+
+// Returns the index of `name` in a header list, adding it if it's missing.
+pub fn find_or_push(list: &mut Vec<String>, name: &str) -> u32 {
+    match list.iter().position(|n| n == name) {
+        Some(index) => index as u32,
+        None => {
+            list.push(name.into());
+            list.len() as u32 - 1
+        }
+    }
+}
+
+// Returns the 1-based owner index of a user, adding them to the owner list if they're missing.
+pub fn find_or_push_owner(save: &mut SaveData, user: &User) -> u32 {
+    let owners = &mut save.header2.brick_owners;
+
+    match owners.iter().position(|o| o.id == user.id) {
+        Some(index) => index as u32 + 1,
+        None => {
+            owners.push(BrickOwner::from(user.clone()));
+            owners.len() as u32
+        }
+    }
+}
+
+// Copies everything but the bricks from a save, so the output looks like an edited copy of it.
+pub fn copy_headers(source: &SaveData) -> SaveData {
+    SaveData {
+        version: source.version,
+        game_version: source.game_version,
+        header1: Header1 {
+            map: source.header1.map.clone(),
+            description: source.header1.description.clone(),
+            author: source.header1.author.clone(),
+            host: source.header1.host.clone(),
+            save_time: source.header1.save_time,
+            brick_count: 0,
+        },
+        header2: Header2 {
+            mods: source.header2.mods.clone(),
+            brick_assets: source.header2.brick_assets.clone(),
+            colors: source.header2.colors.clone(),
+            materials: source.header2.materials.clone(),
+            brick_owners: source.header2.brick_owners.clone(),
+            physical_materials: source.header2.physical_materials.clone(),
+        },
+        preview: source.preview.clone(),
+        bricks: vec![],
+        components: source.components.clone(),
+    }
+}
+
+// Sets each owner's brick count to the number of bricks they own in the save.
+pub fn update_owner_brick_counts(save: &mut SaveData) {
+    let mut counts = vec![0u32; save.header2.brick_owners.len()];
+
+    for brick in &save.bricks {
+        if brick.owner_index > 0 {
+            if let Some(count) = counts.get_mut(brick.owner_index as usize - 1) {
+                *count += 1;
+            }
+        }
+    }
+
+    for (owner, count) in save.header2.brick_owners.iter_mut().zip(counts) {
+        owner.bricks = count;
+    }
+}
@@ -1,13 +1,35 @@
-use std::{env, fs::File, time::Instant};
-use rampifier::{RampifierConfig, Rampifier};
+mod assets;
+mod headers;
+mod options;
+mod palette;
+mod passthrough;
+mod region;
+mod tag;
+
+use assets::{AssetTable, VoxelShape};
+use options::Options;
+use palette::Quantizer;
+
+use std::{env, fs::File, io::{self, IsTerminal, Write}, path::PathBuf, process, time::Instant};
+use rampifier::{
+    components,
+    fill,
+    filters,
+    hollow,
+    grid::VoxelGrid,
+    validate,
+    progress::{Hooks, Progress, Stage},
+    RampifierConfig,
+    Rampifier,
+};
 use brickadia::{
     save::*,
     write::SaveWriter,
     read::SaveReader,
 };
 
-const DEFAULT_SAVE_PATH: &str = "./out.brs";
-const DEFAULT_LOAD_PATH: &str = "./in.brs";
+// Asset table read when --assets isn't given, next to the binary.
+const DEFAULT_ASSET_TABLE_NAME: &str = "assets.txt";
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -16,82 +38,170 @@ fn main() {
     //                   CONSTANTS & CONFIG                    //
     /////////////////////////////////////////////////////////////
 
-    let mut out_save_location = DEFAULT_SAVE_PATH;
-    let mut in_save_location = DEFAULT_LOAD_PATH;
+    let options = match Options::from_args(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\nRun with --help to see all options.", error);
+            process::exit(1);
+        }
+    };
 
-    if args.len() == 2
-    {
-        in_save_location = &args[1];
+    if options.help {
+        println!("{}", options::USAGE);
+        return;
     }
 
-    if args.len() > 2
-    {
-        in_save_location = &args[1];
-        out_save_location = &args[2];
-    }
+    let in_save_location = options.in_path.as_str();
+    let out_save_location = options.out_path.as_str();
 
-    let public = User {
-        name: "rampifier".into(),
-        id: "a8033bee-6c37-4118-b4a6-cecc1d966134".parse().unwrap(),
-    };
+    /////////////////////////////////////////////////////////////
+    //               PASS 1: LOAD BRICKS AS VOX                //
+    /////////////////////////////////////////////////////////////
 
-    let mut out_save = SaveData::default();
+    // Half size of a single voxel, in units.
+    let voxel_size = options.config.brick_size;
+    let (unit_x, unit_y, unit_z) = (voxel_size.0 as i32 * 2, voxel_size.1 as i32 * 2, voxel_size.2 as i32 * 2);
 
-    // set the first header
-    out_save.header1.author = public.clone();
-    out_save.header1.host = Some(public.clone());
-    out_save.header1.description = "This was rampified with rampifier. Save written with brickadia-rs.".into();
+    let fix_brick_pos = |brick: &Brick, shape: &VoxelShape| -> (i32, i32, i32) {
+        let (x, y, z) = brick.position;
+        let (w, l, h) = shape.size;
 
-    // set the second header
-    out_save.header2
-        .brick_owners
-        .push(BrickOwner::from_user_bricks(public.clone(), 100));
+        (
+            (x - w as i32 * unit_x / 2).div_euclid(unit_x),
+            (y - l as i32 * unit_y / 2).div_euclid(unit_y),
+            (z - h as i32 * unit_z / 2).div_euclid(unit_z),
+        )
+    };
 
-    out_save.header2.brick_assets =
-        vec![
-            "PB_DefaultBrick".into(),
-            "PB_DefaultRamp".into(),
-            "PB_DefaultWedge".into(),
-            "PB_DefaultRampCrest".into(),
-        ];
+    // Fixed-size assets that can be voxelized. Extra assets can be listed in a text file.
+    let mut asset_table = AssetTable::builtin();
 
-    // Read colors from sample save.
+    let asset_table_path = match &options.asset_table {
+        Some(path) => Some(PathBuf::from(path)),
+        None => env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join(DEFAULT_ASSET_TABLE_NAME)))
+            .filter(|path| path.exists()),
+    };
 
-    /////////////////////////////////////////////////////////////
-    //               PASS 1: LOAD BRICKS AS VOX                //
-    /////////////////////////////////////////////////////////////
+    if let Some(path) = asset_table_path {
+        match asset_table.load_additions(&path) {
+            Ok(count) => println!("Loaded {} assets from {}\n", count, path.display()),
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        }
+    }
 
-    let fix_brick_pos = |brick: &Brick| -> (i32, i32, i32) {
-        let (mut x, mut  y, mut  z) = brick.position;
-        if let Size::Procedural(w_half, l_half, h_half) = brick.size {
-            x -= w_half as i32;
-            y -= l_half as i32;
-            z -= h_half as i32;
+    // Bricks that aren't plain boxes are copied over as they are instead.
+    if options.passthrough {
+        asset_table.only_listed_procedural();
+    }
 
-            x /= 10;
-            y /= 10;
-            z /= 4;
+    // Ramps from an earlier run are filled back in, so they can be fitted again.
+    // The ramp goes last, so it keeps its crest when both are the same asset.
+    if options.derampify {
+        asset_table.insert_ramp(&options.wedge_asset, 0);
+        asset_table.insert_ramp(&options.ramp_asset, options.config.ramp_crest);
+    }
 
-            return (x, y, z);
+    let file = match File::open(in_save_location) {
+        Ok(file) => file,
+        Err(error) => {
+            eprintln!("Could not read {}, {}", in_save_location, error);
+            process::exit(1);
         }
+    };
 
-        (0, 0, 0)
+    let in_save = match SaveReader::new(file).and_then(|mut reader| reader.read_all()) {
+        Ok(save) => save,
+        Err(error) => {
+            eprintln!("Could not read {}, {}", in_save_location, error);
+            process::exit(1);
+        }
     };
 
-    let file = File::open(in_save_location);
+    let public = tag::rampifier_user();
+
+    // Ramps of an earlier run are read with the assets it saved, so they can be fitted again.
+    let previous_ramps = if options.strip_previous {
+        match tag::previous_settings(&in_save.header1.description) {
+            Some(text) => {
+                let previous = match Options::from_settings_text(text, "save description") {
+                    Ok(previous) => previous,
+                    Err(error) => {
+                        eprintln!("Could not read the settings of the earlier run, {}", error);
+                        process::exit(1);
+                    }
+                };
 
-    let mut reader = match file {
-        Ok(file) => {
-            SaveReader::new(file).unwrap()
-        },
-        Err(error) => {
-            panic!("Could not read {}, {}", in_save_location, error.to_string())
+                if previous.config.brick_size != voxel_size {
+                    let (x, y, z) = previous.config.brick_size;
+                    eprintln!("{} was rampified with --brick-size {},{},{}, strip it with the same brick size.", in_save_location, x, y, z);
+                    process::exit(1);
+                }
+
+                Some((previous.ramp_asset, previous.wedge_asset, previous.config.ramp_crest))
+            }
+            None => {
+                println!("No settings from an earlier run in {}, using the current ramp assets\n", in_save_location);
+                Some((options.ramp_asset.clone(), options.wedge_asset.clone(), options.config.ramp_crest))
+            }
         }
+    }
+    else {
+        None
+    };
+
+    let mut out_save = if options.keep_headers {
+        headers::copy_headers(&in_save)
+    }
+    else {
+        let mut out_save = SaveData::default();
+
+        // set the first header
+        out_save.header1.author = public.clone();
+        out_save.header1.host = Some(public.clone());
+        out_save.header1.description = "This was rampified with rampifier. Save written with brickadia-rs.".into();
+
+        // set the second header
+        out_save.header2
+            .brick_owners
+            .push(BrickOwner::from(public.clone()));
+
+        out_save.header2.brick_assets =
+            vec![
+                "PB_DefaultBrick".into(),
+                "PB_DefaultRamp".into(),
+                "PB_DefaultWedge".into(),
+                "PB_DefaultRampCrest".into(),
+            ];
+
+        // Read colors from sample save.
+        out_save.header2.colors = in_save.header2.colors.clone();
+
+        out_save
     };
 
-    let in_save = reader.read_all().unwrap();
+    // Generated bricks have their own owner, so later runs can find them, and use assets merged into the asset list.
+    let owner_index = headers::find_or_push_owner(&mut out_save, &public);
 
-    out_save.header2.colors = in_save.header2.colors;
+    // Settings later runs can read back, see --strip-previous.
+    out_save.header1.description = tag::with_settings(&out_save.header1.description, &options.generation_settings());
+
+    let config = RampifierConfig {
+        brick_index: headers::find_or_push(&mut out_save.header2.brick_assets, &options.brick_asset),
+        ramp_index: headers::find_or_push(&mut out_save.header2.brick_assets, &options.ramp_asset),
+        wedge_index: headers::find_or_push(&mut out_save.header2.brick_assets, &options.wedge_asset),
+        owner_index,
+        ..options.config.clone()
+    };
+    // The rampifier takes the config, the fill still needs the brick asset and owner.
+    let fill_config = config.clone();
+
+    let material_index = headers::find_or_push(&mut out_save.header2.materials, "BMC_Plastic");
+    let physical_index = headers::find_or_push(&mut out_save.header2.physical_materials, "BPMC_Default");
 
     println!("Converting .brs into voxels...");
 
@@ -101,13 +211,45 @@ fn main() {
     let mut min_bounds = (i32::MAX, i32::MAX, i32::MAX);
     let mut max_bounds = (i32::MIN, i32::MIN, i32::MIN);
 
-    for brick in &in_save.bricks {
-        if let Size::Procedural(w_half, l_half, h_half) = brick.size {
-            let w = w_half as i32 / 5;
-            let l = l_half as i32 / 5;
-            let h = h_half as i32 / 2;
+    // Custom colors are mapped onto the palette depending on the options, once it's known which bricks are rebuilt.
+    let mut quantizer = Quantizer::new(options.color_mode);
+
+    // Bricks that can't be turned into voxels have no shape.
+    let shapes: Vec<Option<VoxelShape>> = in_save.bricks
+        .iter()
+        .map(|brick| {
+            if !quantizer.can_map(&brick.color, &out_save.header2.colors) {
+                return None;
+            }
+
+            // Keep lights, interactables etc. intact instead of turning them into plain bricks.
+            if options.passthrough && !brick.components.is_empty() {
+                return None;
+            }
+
+            if let Some((ramp_asset, wedge_asset, crest)) = &previous_ramps {
+                let name = in_save.header2.brick_assets.get(brick.asset_name_index as usize);
+
+                if tag::is_generated(brick, &in_save) {
+                    if name == Some(ramp_asset) {
+                        return VoxelShape::ramp(brick, voxel_size, *crest);
+                    }
+                    else if name == Some(wedge_asset) {
+                        return VoxelShape::ramp(brick, voxel_size, 0);
+                    }
+                }
+            }
+
+            asset_table.brick_shape(brick, &in_save.header2.brick_assets, voxel_size)
+        })
+        .collect();
+
+    for (brick, shape) in in_save.bricks.iter().zip(&shapes) {
+        if let Some(shape) = shape {
+            let (w, l, h) = shape.size;
+            let (w, l, h) = (w as i32, l as i32, h as i32);
 
-            let pos = fix_brick_pos(&brick);
+            let pos = fix_brick_pos(brick, shape);
 
             min_bounds.0 = min_bounds.0.min(pos.0);
             min_bounds.1 = min_bounds.1.min(pos.1);
@@ -125,89 +267,188 @@ fn main() {
         }
     }
 
+    // Nothing to voxelize, use an empty grid.
+    if min_bounds.0 > max_bounds.0 {
+        min_bounds = (0, 0, 0);
+        max_bounds = (0, 0, 0);
+    }
+
     let grid_size = (
         (max_bounds.0 - min_bounds.0) as usize,
         (max_bounds.1 - min_bounds.1) as usize,
         (max_bounds.2 - min_bounds.2) as usize,
     );
 
-    let get_index = |pos: (usize, usize, usize)| -> usize {
-        pos.0 + pos.1 * grid_size.0 + pos.2 * grid_size.0 * grid_size.1
+    // Grid cells a brick fills, relative to the grid.
+    let brick_voxels = |brick: &Brick, shape: &VoxelShape| -> Vec<(usize, usize, usize)> {
+        let pos = fix_brick_pos(brick, shape);
+        let pos = (
+            (pos.0 - min_bounds.0) as usize,
+            (pos.1 - min_bounds.1) as usize,
+            (pos.2 - min_bounds.2) as usize,
+        );
+
+        let (w, l, h) = shape.size;
+        let mut voxels = Vec::with_capacity(w * l * h);
+
+        for i in 0..w {
+            for j in 0..l {
+                for k in 0..h {
+                    if shape.contains((i, j, k)) {
+                        voxels.push((pos.0 + i, pos.1 + j, pos.2 + k));
+                    }
+                }
+            }
+        }
+
+        voxels
     };
 
-    let mut grid: Vec<Option<u8>> = vec![None; grid_size.0 * grid_size.1 * grid_size.2];
+    let mut grid = VoxelGrid::new(grid_size);
 
-    for brick in &in_save.bricks {
-        if let Size::Procedural(w_half, l_half, h_half) = brick.size {
-            let pos = fix_brick_pos(&brick);
-            let pos = (
-                (pos.0 - min_bounds.0) as usize,
-                (pos.1 - min_bounds.1) as usize,
-                (pos.2 - min_bounds.2) as usize,
-            );
+    // Voxels inside the selected region, and the bricks that have any. Other bricks are left alone.
+    let restricted = !options.region.is_everything();
+    let mut editable = vec![!restricted; grid.len()];
+
+    // Voxels that are always kept as cubes.
+    let locking = !options.lock_colors.is_empty() || !options.lock_owners.is_empty();
+    let mut locked = vec![false; if locking { grid.len() } else { 0 }];
+    let mut selected = vec![!restricted; in_save.bricks.len()];
+
+    // A voxel's center, in world units.
+    let voxel_center = |pos: (usize, usize, usize)| (
+        (pos.0 as i32 + min_bounds.0) * unit_x + unit_x / 2,
+        (pos.1 as i32 + min_bounds.1) * unit_y + unit_y / 2,
+        (pos.2 as i32 + min_bounds.2) * unit_z + unit_z / 2,
+    );
+
+    let mut hooks = console_hooks();
+
+    for (i, (brick, shape)) in in_save.bricks.iter().zip(&shapes).enumerate() {
+        if hooks.is_cancelled() {
+            break;
+        }
 
-            let w = w_half as usize / 5;
-            let l = l_half as usize / 5;
-            let h = h_half as usize / 2;
+        if i % 1024 == 0 {
+            hooks.report(Progress {
+                stage: Stage::Voxelize,
+                done: i,
+                total: in_save.bricks.len(),
+                bricks: 0,
+            });
+        }
 
-            for i in 0..w {
-                for j in 0..l {
-                    for k in 0..h {
-                        let pos = (pos.0 + i, pos.1 + j, pos.2 + k);
+        if let Some(shape) = shape {
+            let voxels = brick_voxels(brick, shape);
 
-                        if let BrickColor::Index(index) = brick.color {
-                            grid[get_index(pos)] = Some(index as u8);
-                        }
+            // Test the voxels' centers, in world units.
+            if restricted && options.region.owns(brick, &in_save) {
+                for &pos in &voxels {
+                    if options.region.contains(voxel_center(pos)) {
+                        editable[grid.index(pos)] = true;
+                        selected[i] = true;
                     }
                 }
             }
-        }
-    }
 
-    println!(" - Done in {}s\n", now.elapsed().as_millis() as f64 / 1000.0);
+            // Only rebuilt bricks can add colors to the palette, kept ones take the closest.
+            let color = if selected[i] {
+                quantizer.palette_index(&brick.color, &mut out_save.header2.colors)
+            }
+            else {
+                quantizer.existing_index(&brick.color, &out_save.header2.colors)
+            };
 
+            // Shapes are only given to bricks with a color that can be mapped.
+            let color = match color {
+                Some(color) => color,
+                None => continue,
+            };
 
-    let box_remove = |g: &mut Vec<Option<u8>>, pos: &(usize, usize, usize), size: &(usize, usize, usize)| {
-        let &(x, y, z) = pos;
-        let &(w, l, h) = size;
+            let lock = locking &&
+                (options.lock_colors.contains(&color) || region::is_owned_by(&options.lock_owners, brick, &in_save));
 
-        for i in 0..w {
-            for j in 0..l {
-                for k in 0..h {
-                    let p = (x + i, y + j, z + k);
+            for pos in voxels {
+                grid.set(pos, Some(color));
 
-                    g[get_index((p.0, p.1, p.2))] = None;
+                if lock {
+                    locked[grid.index(pos)] = true;
                 }
             }
         }
-    };
+        else if restricted {
+            // Bricks that can't be voxelized are selected by their center.
+            selected[i] = options.region.owns(brick, &in_save) && options.region.contains(brick.position);
+        }
+    }
+
+    hooks.report(Progress {
+        stage: Stage::Voxelize,
+        done: in_save.bricks.len(),
+        total: in_save.bricks.len(),
+        bricks: 0,
+    });
 
-    let can_box = |g: &Vec<Option<u8>>, value: u8, pos: &(usize, usize, usize), size: &(usize, usize, usize)| -> bool {
-        let &(w, l, h) = size;
+    if quantizer.added_colors() > 0 {
+        println!(" - Added {} colors to the palette", quantizer.added_colors());
+    }
 
-        if pos.0 + w > grid_size.0 {
-            return false;
+    // Voxels of bricks that are kept as they are. They're still seen when fitting ramps, but not refilled.
+    let mut kept = vec![false; grid.len()];
+
+    for ((brick, shape), &selected) in in_save.bricks.iter().zip(&shapes).zip(&selected) {
+        if let (Some(shape), false) = (shape, selected) {
+            for pos in brick_voxels(brick, shape) {
+                kept[grid.index(pos)] = true;
+            }
         }
-        if pos.1 + l > grid_size.1 {
-            return false;
+    }
+
+    println!(" - Done in {}s\n", now.elapsed().as_millis() as f64 / 1000.0);
+
+    if !options.filters.is_empty() || options.remove_floating || options.min_component_size > 0 {
+        println!("Filtering voxels...");
+
+        let now = Instant::now();
+        let before = grid.clone();
+
+        filters::apply(&mut grid, &options.filters);
+
+        if options.min_component_size > 0 {
+            components::remove_small(&mut grid, options.min_component_size);
         }
-        if pos.2 + h > grid_size.2 {
-            return false;
+
+        if options.remove_floating {
+            components::remove_floating(&mut grid);
         }
 
-        for i in 0..w {
-            for j in 0..l {
-                for k in 0..h {
-                    let pos = (pos.0 + i, pos.1 + j, pos.2 + k);
-                    if g[get_index((pos.0, pos.1, pos.2))] != Some(value) {
-                        return false;
-                    }
-                }
+        // Locked voxels, kept bricks and anything outside the region are put back as they were.
+        let mut changed = 0;
+
+        for index in 0..grid.len() {
+            let (old, new) = (before.cells().get(index), grid.cells().get(index));
+
+            if old == new {
+                continue;
             }
+
+            let outside = restricted && match old {
+                Some(_) => !editable[index],
+                None => !options.region.contains(voxel_center(grid.position(index))),
+            };
+
+            if kept[index] || (locking && locked[index]) || outside {
+                grid.cells_mut().set(index, old);
+                continue;
+            }
+
+            // Voxels the filters add inside the region can be rampified like the rest.
+            editable[index] = true;
+            changed += 1;
         }
 
-        return true;
-    };
+        println!(" - Changed {} voxels in {}s\n", changed, now.elapsed().as_millis() as f64 / 1000.0);
+    }
 
 
     /////////////////////////////////////////////////////////////
@@ -218,102 +459,196 @@ fn main() {
 
     let vox_count = grid.len();
 
+    // Voxels hollowing has to leave alone, taken before the masks are handed to the rampifier.
+    let protected: Vec<bool> = match options.hollow {
+        Some(_) => (0..grid.len()).map(|index| (restricted && !editable[index]) || (locking && locked[index])).collect(),
+        None => Vec::new(),
+    };
+
+    // Voxels the generated bricks should cover, for --validate.
+    let mut expected = options.validate.then(|| grid.clone());
+
+    // The rampifier works on the grid in place, it's free to use again once the rampifier is dropped.
     let mut rampifier = Rampifier::new(
-        grid_size,
-        grid,
-        RampifierConfig::default()
-    );
+        &mut grid,
+        config
+    ).with_hooks(hooks);
+
+    if restricted {
+        rampifier = rampifier.with_region(editable);
+    }
+
+    if locking {
+        rampifier = rampifier.with_lock_mask(locked);
+    }
 
     let now = Instant::now();
 
     // Generate ramps for floor and ceiling.
-    let ramps = &mut rampifier.generate_ramps(true);
-    let ramps2 = &mut rampifier.generate_ramps(false);
+    let mut ramp_count = 0;
 
-    let ramp_count = ramps.len();
-    let ramp2_count = ramps2.len();
+    if options.floor {
+        let ramps = &mut rampifier.generate_ramps(true);
+        ramp_count += ramps.len();
+        out_save.bricks.append(ramps);
+    }
 
-    out_save.bricks.append(ramps);
-    out_save.bricks.append(ramps2);
+    if options.ceiling {
+        let ramps = &mut rampifier.generate_ramps(false);
+        ramp_count += ramps.len();
+        out_save.bricks.append(ramps);
+    }
 
     println!(" - Processed {} voxels", vox_count);
-    println!(" - Generated {} ramps in {}s\n", ramp_count + ramp2_count, now.elapsed().as_millis() as f64 / 1000.0);
+    println!(" - Generated {} ramps in {}s\n", ramp_count, now.elapsed().as_millis() as f64 / 1000.0);
 
     // Sets the voxels occupied by ramps to empty.
     rampifier.remove_occupied_voxels();
 
-    // Move grid back out of the rampifier to do further processing.
-    let mut grid = rampifier.move_grid();
+    let mut hooks = rampifier.take_hooks();
+    drop(rampifier);
+
+    // Kept bricks are still in the grid here, so voxels behind them count as hidden.
+    if let Some(shell) = options.hollow {
+        println!("Hollowing...");
+
+        let now = Instant::now();
+        let before = grid.clone();
+
+        hollow::hollow(&mut grid, shell, options.hollow_mode);
+
+        let mut changed = 0;
+
+        for (index, &protected) in protected.iter().enumerate() {
+            let old = before.cells().get(index);
+
+            if old == grid.cells().get(index) {
+                continue;
+            }
+
+            if protected {
+                grid.cells_mut().set(index, old);
+            }
+            else {
+                changed += 1;
+
+                // Removed voxels aren't expected to be covered anymore.
+                if let Some(expected) = &mut expected {
+                    expected.cells_mut().set(index, grid.cells().get(index));
+                }
+            }
+        }
+
+        println!(" - Hollowed {} voxels in {}s\n", changed, now.elapsed().as_millis() as f64 / 1000.0);
+    }
+
+    for (index, &kept) in kept.iter().enumerate() {
+        if kept {
+            grid.cells_mut().set(index, None);
+
+            if let Some(expected) = &mut expected {
+                expected.cells_mut().set(index, None);
+            }
+        }
+    }
 
 
     /////////////////////////////////////////////////////////////
     //         PASS 3: GENERATE OPTIMIZED BRICK FILL           //
     /////////////////////////////////////////////////////////////
 
-    println!("Filling Gaps...");
+    if options.fill {
+        println!("Filling Gaps...");
 
-    for z in 0..grid_size.2 {
-        for y in 0..grid_size.1 {
-            for x in 0..grid_size.0 {
-                let mut brick = Brick::default();
+        let bricks = &mut fill::fill_bricks(&mut grid, &fill_config, &mut hooks);
+        out_save.bricks.append(bricks);
 
-                if let Some(val) = grid[get_index((x, y, z))] {
-                    let mut w = 1;
-                    let mut l = 1;
-                    let mut h = 1;
+        println!(" - Gaps filled.");
+    }
 
-                    // todo: this can be done way better, but this is a shitty quick way to optimize bricks
-                    while can_box(&grid, val, &(x, y, z), &(w, l, h)) && h <= 64 {
-                        h += 1;
-                    }
+    // Only generated bricks are in the save so far, still positioned relative to the grid.
+    if let Some(expected) = &expected {
+        println!("Validating...");
+
+        let report = validate::validate(&out_save.bricks, &fill_config, expected);
+
+        // Positions in studs on every axis, like --region.
+        let voxel_pos = |(x, y, z): (usize, usize, usize)| (
+            ((x as i32 + min_bounds.0) * unit_x) as f32 / 10.0,
+            ((y as i32 + min_bounds.1) * unit_y) as f32 / 10.0,
+            ((z as i32 + min_bounds.2) * unit_z) as f32 / 10.0,
+        );
 
-                    h -= 1;
+        for overlap in report.overlaps.iter().take(10) {
+            println!(" - Bricks {} and {} overlap by {} voxels near {:?}", overlap.first, overlap.second, overlap.volume, voxel_pos(overlap.voxel));
+        }
 
-                    if h > 0 {
-                        while can_box(&grid, val, &(x, y, z), &(w, l, h)) && w <= 64 {
-                            w += 1;
-                        }
+        for &gap in report.gaps.iter().take(10) {
+            println!(" - Nothing covers the voxel at {:?}", voxel_pos(gap));
+        }
 
-                        w -= 1;
+        println!(
+            " - {} overlaps, {} gaps, {} bricks outside the grid, {} bricks not checked\n",
+            report.overlaps.len(),
+            report.gaps.len(),
+            report.out_of_grid.len(),
+            report.skipped.len(),
+        );
+    }
+
+    for brick in &mut out_save.bricks {
+        brick.position.0 += min_bounds.0 * unit_x;
+        brick.position.1 += min_bounds.1 * unit_y;
+        brick.position.2 += min_bounds.2 * unit_z;
+        brick.material_index = material_index;
+        brick.physical_index = physical_index;
+    }
 
-                        if w > 0 {
-                            while can_box(&grid, val, &(x, y, z), &(w, l, h)) && l <= 64 {
-                                l += 1;
-                            }
+    // Everything before here was generated in this run, passed through bricks come after.
+    let generated = out_save.bricks.len();
 
-                            l -= 1;
+    // Everything outside the region is kept, whether it could be voxelized or not.
+    if restricted {
+        let outside = in_save.bricks
+            .iter()
+            .zip(&selected)
+            .filter(|(_, &selected)| !selected)
+            .map(|(brick, _)| brick);
 
-                            if l > 0 {
-                                box_remove(&mut grid, &(x, y, z), &(w, l, h));
+        let count = passthrough::copy_bricks(&mut out_save, &in_save, outside);
 
-                                let size = (w as u32 * 5, l as u32 * 5, h as u32 * 2);
-                                {
-                                    let (x, y, z) = (x as i32 * 10, y as i32 * 10, z as i32 * 4);
+        println!(" - Kept {} bricks outside the region.", count);
+    }
 
-                                    brick.position = (x + size.0 as i32, y + size.1 as i32, z + size.2 as i32);
-                                    brick.size = Size::Procedural(size.0, size.1, size.2);
-                                }
+    // Bricks inside the region that can't be voxelized.
+    let unconverted = in_save.bricks
+        .iter()
+        .zip(&shapes)
+        .zip(&selected)
+        .filter(|((_, shape), &selected)| shape.is_none() && selected)
+        .map(|((brick, _), _)| brick);
 
-                                brick.color = BrickColor::Index(val as u32);
-                                brick.owner_index = 1;
+    if options.passthrough {
+        let count = passthrough::copy_bricks(&mut out_save, &in_save, unconverted);
 
-                                out_save.bricks.push(brick);
-                            }
-                        }
-                    }
-                }
-            }
+        println!(" - Passed through {} bricks.", count);
+    }
+    else {
+        let dropped = unconverted.count();
+
+        if dropped > 0 {
+            println!(" - Left out {} bricks that can't be voxelized, pass --passthrough to keep them.", dropped);
         }
     }
 
-    println!(" - Gaps filled.");
+    if options.mark_bricks {
+        let count = tag::mark_generated(&mut out_save, 0..generated);
 
-    for mut brick in &mut out_save.bricks {
-        brick.position.0 += min_bounds.0 * 10;
-        brick.position.1 += min_bounds.1 * 10;
-        brick.position.2 += min_bounds.2 * 4;
+        println!(" - Marked {} generated bricks.", count);
     }
 
+    headers::update_owner_brick_counts(&mut out_save);
+
     // write out the save
     let file = File::create(out_save_location);
 
@@ -326,7 +661,28 @@ fn main() {
             println!("Save written to {}", out_save_location);
         },
         Err(error) => {
-            println!("Could not write to {}, {}", out_save_location, error.to_string())
+            println!("Could not write to {}, {}", out_save_location, error)
         }
     }
 }
+
+// Shows a progress line on stderr while a stage runs, when it's a terminal.
+fn console_hooks() -> Hooks {
+    if !io::stderr().is_terminal() {
+        return Hooks::new();
+    }
+
+    Hooks::new().with_observer(|progress: &Progress| {
+        let mut stderr = io::stderr();
+
+        if progress.done >= progress.total {
+            // Clear the line so the stage's summary prints over it.
+            let _ = write!(stderr, "\r{:40}\r", "");
+        }
+        else {
+            let _ = write!(stderr, "\r - {:?}: {:.0}%", progress.stage, progress.fraction() * 100.0);
+        }
+
+        let _ = stderr.flush();
+    })
+}
@@ -0,0 +1,753 @@
+use std::fs;
+use rampifier::{filters::Filter, hollow::HollowMode, ColorRule, RampColorSource, RampifierConfig, ScanMode, Symmetry, TieBreak};
+use crate::palette::ColorMode;
+use crate::region::Region;
+
+const DEFAULT_SAVE_PATH: &str = "./out.brs";
+const DEFAULT_LOAD_PATH: &str = "./in.brs";
+
+pub const USAGE: &str = "\
+Usage: plate-rampifier [options] [in.brs] [out.brs]
+
+Rampifies a plate aligned save. Paths default to ./in.brs and ./out.brs.
+
+General:
+  --help                       Show this message.
+  --config <path>              Read options from a file, one `name = value` per line.
+                               Options given on the command line take precedence.
+  --assets <path>              Extra fixed-size assets to voxelize (default assets.txt next
+                               to the binary).
+  --passthrough                Copy bricks that can't be rampified into the output.
+  --derampify                  Turn ramps and wedges back into voxels, so a rampified save
+                               can be rampified again with other settings.
+  --strip-previous             Turn the bricks of an earlier run back into voxels, reading its
+                               ramps with the settings saved in the description.
+  --mark-bricks                Also add a custom Rampifier_Generated component to generated
+                               bricks. Brickadia doesn't define it, generated bricks are
+                               already told apart by their owner.
+  --validate                   Check the generated bricks for overlaps, gaps and bricks
+                               outside the grid.
+  --keep-headers               Keep the input save's headers, owners and screenshot.
+  --quantize-colors            Map custom colors to the closest palette color.
+  --add-colors <n>             Add up to n custom colors to the palette.
+
+Region:
+  --region <x1,y1,z1,x2,y2,z2> Only rampify inside this box, in studs. Can be repeated.
+  --region-owner <name|id>     Only rampify bricks owned by this user. Can be repeated.
+                               Bricks outside the region are copied unchanged.
+  --lock-colors <list>         Palette indices that are always kept as cubes.
+  --lock-owner <name|id>       Keep bricks owned by this user as cubes. Can be repeated.
+
+Filters:
+  --filters <list>             Clean up the voxels before fitting ramps, in order. Each is
+                               name or name:n, from open:steps, close:steps,
+                               isolated:neighbours (default 2), holes:neighbours (default 5)
+                               and smooth:rounds. Steps and rounds default to 1.
+  --remove-floating            Remove groups of voxels that don't reach the bottom of the save.
+  --min-component-size <n>     Remove groups of connected voxels smaller than n voxels.
+  --hollow <n>                 Hollow out voxels that can't be seen from outside, keeping a
+                               shell n voxels thick.
+  --hollow-mode <mode>         merge (default) fills hidden voxels with one color so they take
+                               fewer bricks, remove leaves them empty.
+
+Generation:
+  --preset <name>              plate (default), micro or x4cube.
+  --passes <list>              Ramp passes to run: floor, ceiling or floor,ceiling (default).
+  --fill / --no-fill           Fill the remaining voxels with bricks (default on).
+  --brick-asset <name>         Cube asset (default PB_DefaultBrick).
+  --ramp-asset <name>          Ramp asset (default PB_DefaultRamp).
+  --wedge-asset <name>         Wedge asset (default PB_DefaultWedge).
+  --brick-size <x,y,z>         Half size of one voxel's brick, in units.
+  --ramp-crest <n>             Length of the flat top of the ramp asset, in voxels. 1 for
+                               PB_DefaultRamp (plate), 0 for micro wedges (micro, x4cube).
+  --ramp-max-width <n>
+  --ramp-max-run <n>           Longest ramp, in voxels.
+  --ramp-max-rise <n>          Tallest ramp, in voxels.
+  --ramp-min-rise <n>          Shortest ramp, in voxels.
+  --ramp-rise-step <n>
+  --wedge-max-rise <n>
+  --wedge-rise-step <n>
+  --use-wedge / --no-use-wedge
+  --support-colors <list>      Palette indices that ramps rest on but never become ramps.
+  --block-colors <list>        Palette indices that never become ramps and stop ramps.
+  --ramp-colors <list>         Palette indices that can become ramps again (the default).
+  --stop-at-color-change       End ramps where the color changes.
+  --ramp-color <source>        Color ramps by the most common covered color (mode, default)
+                               or by the color of their top surface (surface).
+  --tie-break <rule>           Pick between equally steep directions by table order (table,
+                               default) or by ramp size, then towards the middle (neutral).
+  --scan <mode>                single (default), mirrored (four scans, keep the best) or
+                               symmetric (whole layers at once, with --tie-break neutral
+                               symmetric saves get symmetric ramps).
+  --symmetry <axis>            Mirror ramps across the middle of the save along x or y,
+                               or none (default).
+
+Flags can also be written as --name=true or --name=false.";
+
+// Brick generation presets, see `RampifierConfig`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Preset {
+    Plate,
+    Micro,
+    X4Cube,
+}
+
+impl Preset {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "plate" => Some(Preset::Plate),
+            "micro" => Some(Preset::Micro),
+            "x4cube" => Some(Preset::X4Cube),
+            _ => None,
+        }
+    }
+
+    // Asset indices are resolved against the output save later.
+    fn config(&self) -> RampifierConfig {
+        match self {
+            Preset::Plate => RampifierConfig::default(),
+            Preset::Micro => RampifierConfig::micro(0, 0),
+            Preset::X4Cube => RampifierConfig::x4cube(0, 0),
+        }
+    }
+
+    // Cube, ramp and wedge asset names.
+    fn assets(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Preset::Plate => ("PB_DefaultBrick", "PB_DefaultRamp", "PB_DefaultWedge"),
+            Preset::Micro | Preset::X4Cube => ("PB_DefaultMicroBrick", "PB_DefaultMicroWedge", "PB_DefaultMicroWedge"),
+        }
+    }
+}
+
+pub struct Options {
+    // Path of the save to rampify.
+    pub in_path: String,
+
+    // Path the rampified save is written to.
+    pub out_path: String,
+
+    // File with extra assets to voxelize. None reads assets.txt next to the binary, if there is one.
+    pub asset_table: Option<String>,
+
+    // Copy bricks that can't be rampified into the output unchanged.
+    pub passthrough: bool,
+
+    // Voxelize ramps and wedges made with the ramp and wedge assets.
+    pub derampify: bool,
+
+    // Voxelize bricks generated by an earlier run, with the settings it saved in the description.
+    pub strip_previous: bool,
+
+    // Tag generated bricks with a marker component, besides giving them their own owner.
+    pub mark_bricks: bool,
+
+    // Check the generated bricks against the voxels they were made from.
+    pub validate: bool,
+
+    // Keep the input save's headers, owners, map and screenshot instead of writing a new save.
+    pub keep_headers: bool,
+
+    // How bricks with custom colors are handled.
+    pub color_mode: ColorMode,
+
+    // Part of the save to rampify.
+    pub region: Region,
+
+    // Bricks with these colors or owners never turn into ramps.
+    pub lock_colors: Vec<u8>,
+    pub lock_owners: Vec<String>,
+
+    // Clean-up filters run on the voxels before fitting ramps.
+    pub filters: Vec<Filter>,
+
+    // Connected voxels that are removed before fitting ramps: floating ones, and ones smaller than this.
+    pub remove_floating: bool,
+    pub min_component_size: usize,
+
+    // Shell thickness to hollow out hidden voxels to, and what happens to them.
+    pub hollow: Option<usize>,
+    pub hollow_mode: HollowMode,
+
+    // Ramp generation settings. Asset indices are filled in from the asset names.
+    pub config: RampifierConfig,
+
+    pub brick_asset: String,
+    pub ramp_asset: String,
+    pub wedge_asset: String,
+
+    // Which ramp passes to run.
+    pub floor: bool,
+    pub ceiling: bool,
+
+    // Fill the voxels left after generating ramps with bricks.
+    pub fill: bool,
+
+    // Print usage and exit.
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::from_preset(Preset::Plate)
+    }
+}
+
+// A `name = value` setting, from either the command line or a config file.
+struct Setting {
+    name: String,
+    value: Option<String>,
+}
+
+fn parse_bool(name: &str, value: Option<&str>) -> Result<bool, String> {
+    match value {
+        None | Some("true") | Some("on") | Some("yes") => Ok(true),
+        Some("false") | Some("off") | Some("no") => Ok(false),
+        Some(value) => Err(format!("Invalid value {} for --{}, expected true or false", value, name)),
+    }
+}
+
+fn parse_number(name: &str, value: Option<&str>) -> Result<usize, String> {
+    let value = value.ok_or_else(|| format!("--{} expects a number", name))?;
+    value.parse().map_err(|_| format!("Invalid value {} for --{}, expected a number", value, name))
+}
+
+fn parse_string(name: &str, value: Option<&str>) -> Result<String, String> {
+    value
+        .map(|value| value.to_string())
+        .ok_or_else(|| format!("--{} expects a value", name))
+}
+
+fn parse_brick_size(name: &str, value: Option<&str>) -> Result<(u32, u32, u32), String> {
+    let value = value.ok_or_else(|| format!("--{} expects x,y,z", name))?;
+    let invalid = || format!("Invalid value {} for --{}, expected x,y,z", value, name);
+
+    let parts: Vec<u32> = value
+        .split(',')
+        .map(|part| part.trim().parse::<u32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+
+    match parts[..] {
+        [x, y, z] if x > 0 && y > 0 && z > 0 => Ok((x, y, z)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_color_list(name: &str, value: Option<&str>) -> Result<Vec<u8>, String> {
+    let value = value.ok_or_else(|| format!("--{} expects a list of palette indices", name))?;
+
+    value
+        .split(',')
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<u8>().map_err(|_| format!("Invalid palette index {} for --{}", part, name)))
+        .collect()
+}
+
+// Parses a list like `isolated,holes:6,smooth:2`.
+fn parse_filter_list(name: &str, value: Option<&str>) -> Result<Vec<Filter>, String> {
+    let value = value.ok_or_else(|| format!("--{} expects a list of filters", name))?;
+
+    value
+        .split(',')
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (filter, amount) = match part.split_once(':') {
+                Some((filter, amount)) => {
+                    let amount = amount
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid amount {} for filter {} in --{}", amount, filter, name))?;
+                    (filter, Some(amount))
+                }
+                None => (part, None),
+            };
+
+            match filter {
+                "open" => Ok(Filter::Open(amount.unwrap_or(1))),
+                "close" => Ok(Filter::Close(amount.unwrap_or(1))),
+                "isolated" => Ok(Filter::RemoveIsolated(amount.unwrap_or(2))),
+                "holes" => Ok(Filter::FillHoles(amount.unwrap_or(5))),
+                "smooth" => Ok(Filter::Smooth(amount.unwrap_or(1))),
+                _ => Err(format!("Unknown filter {}, expected open, close, isolated, holes or smooth", filter)),
+            }
+        })
+        .collect()
+}
+
+// Options that take a value on the command line. Everything else is a flag.
+fn takes_value(name: &str) -> bool {
+    !matches!(
+        name,
+        "help" | "passthrough" | "derampify" | "strip-previous" | "mark-bricks" | "validate" | "keep-headers" | "quantize-colors" | "fill" | "use-wedge" |
+            "stop-at-color-change" | "remove-floating"
+    )
+}
+
+// Reads `name = value` settings from a config file.
+fn read_config_file(path: &str) -> Result<Vec<Setting>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}, {}", path, e))?;
+
+    parse_settings(&text, path)
+}
+
+// Parses `name = value` lines, naming `source` in errors. Blank lines and `#` comments are skipped.
+fn parse_settings(text: &str, source: &str) -> Result<Vec<Setting>, String> {
+    let mut settings = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().to_string())),
+            None => (line, None),
+        };
+
+        let name = name.trim_start_matches("--");
+
+        if name == "config" {
+            return Err(format!("{}:{}: config files can't include other config files", source, line_number + 1));
+        }
+
+        settings.push(Setting { name: name.into(), value });
+    }
+
+    Ok(settings)
+}
+
+impl Options {
+    fn from_preset(preset: Preset) -> Self {
+        let (brick_asset, ramp_asset, wedge_asset) = preset.assets();
+
+        Self {
+            in_path: DEFAULT_LOAD_PATH.into(),
+            out_path: DEFAULT_SAVE_PATH.into(),
+            asset_table: None,
+            passthrough: false,
+            derampify: false,
+            strip_previous: false,
+            mark_bricks: false,
+            validate: false,
+            keep_headers: false,
+            color_mode: ColorMode::Skip,
+            region: Region::default(),
+            lock_colors: Vec::new(),
+            lock_owners: Vec::new(),
+            filters: Vec::new(),
+            remove_floating: false,
+            min_component_size: 0,
+            hollow: None,
+            hollow_mode: HollowMode::MergeAcrossColors,
+            config: preset.config(),
+            brick_asset: brick_asset.into(),
+            ramp_asset: ramp_asset.into(),
+            wedge_asset: wedge_asset.into(),
+            floor: true,
+            ceiling: true,
+            fill: true,
+            help: false,
+        }
+    }
+
+    // Parses command line arguments, skipping the binary name.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut settings = Vec::new();
+        let mut config_files = Vec::new();
+        let mut paths = Vec::new();
+
+        let mut args = args.iter().skip(1);
+
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => {
+                    paths.push(arg.clone());
+                    continue;
+                }
+            };
+
+            let (name, value) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None if takes_value(name) && !name.starts_with("no-") => (name, args.next().cloned()),
+                None => (name, None),
+            };
+
+            if name == "config" {
+                config_files.push(parse_string(name, value.as_deref())?);
+            }
+            else {
+                settings.push(Setting { name: name.into(), value });
+            }
+        }
+
+        // Config files are applied first, so the command line can override them.
+        let mut all_settings = Vec::new();
+        for path in &config_files {
+            all_settings.append(&mut read_config_file(path)?);
+        }
+        all_settings.append(&mut settings);
+
+        let mut options = Self::from_settings(&all_settings)?;
+
+        match paths.len() {
+            0 => (),
+            1 => options.in_path = paths.remove(0),
+            2 => {
+                options.in_path = paths.remove(0);
+                options.out_path = paths.remove(0);
+            }
+            _ => return Err(format!("Unexpected argument {}", paths[2])),
+        }
+
+        if options.config.ramp_max_run == 0 || options.config.ramp_max_rise == 0 {
+            return Err("--ramp-max-run and --ramp-max-rise must be at least 1".into());
+        }
+
+        Ok(options)
+    }
+
+    // Applies settings on top of the defaults of the preset they pick.
+    fn from_settings(settings: &[Setting]) -> Result<Self, String> {
+        let mut preset = Preset::Plate;
+        for setting in settings {
+            if setting.name == "preset" {
+                let value = setting.value.as_deref().unwrap_or("");
+                preset = Preset::parse(value).ok_or_else(|| format!("Unknown preset {}", value))?;
+            }
+        }
+
+        let mut options = Self::from_preset(preset);
+
+        for setting in settings {
+            options.set(&setting.name, setting.value.as_deref())?;
+        }
+
+        Ok(options)
+    }
+
+    // Reads options written by `generation_settings`, like from a config file.
+    pub fn from_settings_text(text: &str, source: &str) -> Result<Self, String> {
+        Self::from_settings(&parse_settings(text, source)?)
+    }
+
+    /* The options that decide which bricks are generated, as `name = value` lines that
+     * `from_settings_text` and config files read back. Asset indices and owners aren't
+     * included, they depend on the save.
+     */
+    pub fn generation_settings(&self) -> String {
+        let config = &self.config;
+
+        let passes: Vec<&str> = [(self.floor, "floor"), (self.ceiling, "ceiling")]
+            .iter()
+            .filter(|(run, _)| *run)
+            .map(|&(_, pass)| pass)
+            .collect();
+
+        let (x, y, z) = config.brick_size;
+
+        let mut lines = vec![
+            format!("passes = {}", if passes.is_empty() { "none".to_string() } else { passes.join(",") }),
+            format!("fill = {}", self.fill),
+            format!("brick-asset = {}", self.brick_asset),
+            format!("ramp-asset = {}", self.ramp_asset),
+            format!("wedge-asset = {}", self.wedge_asset),
+            format!("brick-size = {},{},{}", x, y, z),
+            format!("ramp-crest = {}", config.ramp_crest),
+            format!("ramp-max-width = {}", config.ramp_max_width),
+            format!("ramp-max-run = {}", config.ramp_max_run),
+            format!("ramp-max-rise = {}", config.ramp_max_rise),
+            format!("ramp-min-rise = {}", config.ramp_min_rise),
+            format!("ramp-rise-step = {}", config.ramp_rise_step),
+            format!("wedge-max-rise = {}", config.wedge_max_rise),
+            format!("wedge-rise-step = {}", config.wedge_rise_step),
+            format!("use-wedge = {}", config.use_wedge),
+            format!("stop-at-color-change = {}", config.stop_at_color_change),
+            format!("ramp-color = {}", match config.ramp_color {
+                RampColorSource::Mode => "mode",
+                RampColorSource::Surface => "surface",
+            }),
+            format!("tie-break = {}", match config.tie_break {
+                TieBreak::TableOrder => "table",
+                TieBreak::Neutral => "neutral",
+            }),
+            format!("scan = {}", match config.scan_mode {
+                ScanMode::Single => "single",
+                ScanMode::Mirrored => "mirrored",
+                ScanMode::Symmetric => "symmetric",
+            }),
+            format!("symmetry = {}", match config.symmetry {
+                Symmetry::None => "none",
+                Symmetry::MirrorX => "x",
+                Symmetry::MirrorY => "y",
+            }),
+        ];
+
+        for (name, rule) in [("support-colors", ColorRule::Support), ("block-colors", ColorRule::Block)] {
+            let mut colors: Vec<u8> = config.color_rules
+                .iter()
+                .filter(|&(_, &r)| r == rule)
+                .map(|(&color, _)| color)
+                .collect();
+
+            if !colors.is_empty() {
+                colors.sort_unstable();
+                let colors: Vec<String> = colors.iter().map(|color| color.to_string()).collect();
+                lines.push(format!("{} = {}", name, colors.join(",")));
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        // --no-name is the same as --name=false.
+        if let Some(flag) = name.strip_prefix("no-") {
+            if value.is_some() || takes_value(flag) {
+                return Err(format!("Unknown option --{}", name));
+            }
+            return self.set(flag, Some("false"));
+        }
+
+        let config = &mut self.config;
+
+        match name {
+            "help" => self.help = parse_bool(name, value)?,
+            "preset" => (),
+            "assets" => self.asset_table = Some(parse_string(name, value)?),
+            "passthrough" => self.passthrough = parse_bool(name, value)?,
+            "derampify" => self.derampify = parse_bool(name, value)?,
+            "strip-previous" => self.strip_previous = parse_bool(name, value)?,
+            "mark-bricks" => self.mark_bricks = parse_bool(name, value)?,
+            "validate" => self.validate = parse_bool(name, value)?,
+            "keep-headers" => self.keep_headers = parse_bool(name, value)?,
+            "quantize-colors" => {
+                self.color_mode = if parse_bool(name, value)? { ColorMode::Nearest } else { ColorMode::Skip };
+            }
+            "add-colors" => self.color_mode = ColorMode::Append(parse_number(name, value)?),
+            "region" => {
+                let value = parse_string(name, value)?;
+                let region_box = Region::parse_box(&value)
+                    .ok_or_else(|| format!("Invalid value {} for --{}, expected x1,y1,z1,x2,y2,z2", value, name))?;
+                self.region.boxes.push(region_box);
+            }
+            "region-owner" => self.region.owners.push(parse_string(name, value)?),
+            "lock-colors" => self.lock_colors.append(&mut parse_color_list(name, value)?),
+            "lock-owner" => self.lock_owners.push(parse_string(name, value)?),
+            "filters" => self.filters = parse_filter_list(name, value)?,
+            "remove-floating" => self.remove_floating = parse_bool(name, value)?,
+            "min-component-size" => self.min_component_size = parse_number(name, value)?,
+            "hollow" => self.hollow = Some(parse_number(name, value)?),
+            "hollow-mode" => {
+                self.hollow_mode = match parse_string(name, value)?.as_str() {
+                    "merge" => HollowMode::MergeAcrossColors,
+                    "remove" => HollowMode::Remove,
+                    value => return Err(format!("Unknown hollow mode {}, expected merge or remove", value)),
+                };
+            }
+            "passes" => {
+                let passes = parse_string(name, value)?;
+                self.floor = false;
+                self.ceiling = false;
+
+                for pass in passes.split(',').map(str::trim) {
+                    match pass {
+                        "floor" => self.floor = true,
+                        "ceiling" => self.ceiling = true,
+                        "none" => (),
+                        _ => return Err(format!("Unknown pass {}, expected floor or ceiling", pass)),
+                    }
+                }
+            }
+            "fill" => self.fill = parse_bool(name, value)?,
+            "brick-asset" => self.brick_asset = parse_string(name, value)?,
+            "ramp-asset" => self.ramp_asset = parse_string(name, value)?,
+            "wedge-asset" => self.wedge_asset = parse_string(name, value)?,
+            "brick-size" => config.brick_size = parse_brick_size(name, value)?,
+            "ramp-crest" => config.ramp_crest = parse_number(name, value)?,
+            "ramp-max-width" => config.ramp_max_width = parse_number(name, value)?,
+            "ramp-max-run" => config.ramp_max_run = parse_number(name, value)?,
+            "ramp-max-rise" => config.ramp_max_rise = parse_number(name, value)?,
+            "ramp-min-rise" => config.ramp_min_rise = parse_number(name, value)?,
+            "ramp-rise-step" => config.ramp_rise_step = parse_number(name, value)?,
+            "wedge-max-rise" => config.wedge_max_rise = parse_number(name, value)?,
+            "wedge-rise-step" => config.wedge_rise_step = parse_number(name, value)?,
+            "use-wedge" => config.use_wedge = parse_bool(name, value)?,
+            "stop-at-color-change" => config.stop_at_color_change = parse_bool(name, value)?,
+            "ramp-color" => {
+                config.ramp_color = match parse_string(name, value)?.as_str() {
+                    "mode" => RampColorSource::Mode,
+                    "surface" => RampColorSource::Surface,
+                    value => return Err(format!("Unknown ramp color {}, expected mode or surface", value)),
+                };
+            }
+            "tie-break" => {
+                config.tie_break = match parse_string(name, value)?.as_str() {
+                    "table" => TieBreak::TableOrder,
+                    "neutral" => TieBreak::Neutral,
+                    value => return Err(format!("Unknown tie break {}, expected table or neutral", value)),
+                };
+            }
+            "scan" => {
+                config.scan_mode = match parse_string(name, value)?.as_str() {
+                    "single" => ScanMode::Single,
+                    "mirrored" => ScanMode::Mirrored,
+                    "symmetric" => ScanMode::Symmetric,
+                    value => return Err(format!("Unknown scan mode {}, expected single, mirrored or symmetric", value)),
+                };
+            }
+            "symmetry" => {
+                config.symmetry = match parse_string(name, value)?.as_str() {
+                    "none" => Symmetry::None,
+                    "x" => Symmetry::MirrorX,
+                    "y" => Symmetry::MirrorY,
+                    value => return Err(format!("Unknown symmetry {}, expected x, y or none", value)),
+                };
+            }
+            "ramp-colors" | "support-colors" | "block-colors" => {
+                let rule = match name {
+                    "support-colors" => ColorRule::Support,
+                    "block-colors" => ColorRule::Block,
+                    _ => ColorRule::Ramp,
+                };
+
+                for color in parse_color_list(name, value)? {
+                    config.color_rules.insert(color, rule);
+                }
+            }
+            _ => return Err(format!("Unknown option --{}", name)),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = std::iter::once("plate-rampifier")
+            .chain(args.split_whitespace())
+            .map(String::from)
+            .collect();
+
+        Options::from_args(&args)
+    }
+
+    #[test]
+    fn paths_default_and_can_be_given() {
+        let options = parse("").unwrap();
+        assert_eq!((options.in_path.as_str(), options.out_path.as_str()), ("./in.brs", "./out.brs"));
+
+        let options = parse("a.brs").unwrap();
+        assert_eq!((options.in_path.as_str(), options.out_path.as_str()), ("a.brs", "./out.brs"));
+
+        assert_eq!(parse("a.brs b.brs c.brs").err().unwrap(), "Unexpected argument c.brs");
+    }
+
+    #[test]
+    fn values_can_follow_or_be_joined() {
+        let options = parse("--ramp-max-run 6 --ramp-max-rise=3 --brick-size 10,10,4").unwrap();
+
+        assert_eq!(options.config.ramp_max_run, 6);
+        assert_eq!(options.config.ramp_max_rise, 3);
+        assert_eq!(options.config.brick_size, (10, 10, 4));
+    }
+
+    #[test]
+    fn flags_can_be_turned_off() {
+        let options = parse("--no-fill --use-wedge=false --passthrough=yes").unwrap();
+
+        assert!(!options.fill);
+        assert!(!options.config.use_wedge);
+        assert!(options.passthrough);
+    }
+
+    #[test]
+    fn invalid_options_are_errors() {
+        assert_eq!(parse("--frobnicate").err().unwrap(), "Unknown option --frobnicate");
+        assert_eq!(parse("--no-ramp-max-run").err().unwrap(), "Unknown option --no-ramp-max-run");
+        assert_eq!(parse("--preset huge").err().unwrap(), "Unknown preset huge");
+        assert_eq!(parse("--ramp-max-run 0").err().unwrap(), "--ramp-max-run and --ramp-max-rise must be at least 1");
+        assert!(parse("--brick-size 5,5").is_err());
+        assert!(parse("--passes floor,walls").is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_config_files() {
+        let path = std::env::temp_dir().join(format!("plate-rampifier-options-{}.txt", std::process::id()));
+        fs::write(&path, "ramp-max-run = 6\nramp-max-rise = 3\n").unwrap();
+
+        let options = parse(&format!("--ramp-max-rise 5 --config {}", path.display()));
+        fs::remove_file(&path).unwrap();
+
+        let options = options.unwrap();
+        assert_eq!(options.config.ramp_max_run, 6);
+        assert_eq!(options.config.ramp_max_rise, 5);
+    }
+
+    #[test]
+    fn preset_applies_before_other_options() {
+        let options = parse("--brick-size 2,2,2 --preset micro").unwrap();
+
+        assert_eq!(options.config.brick_size, (2, 2, 2));
+        assert_eq!(options.brick_asset, "PB_DefaultMicroBrick");
+    }
+
+    #[test]
+    fn generation_settings_round_trip() {
+        let options = parse(
+            "--preset micro --passes ceiling --no-fill --ramp-asset PB_MyRamp --ramp-crest 2 --ramp-max-run 6 \
+             --scan symmetric --tie-break neutral --symmetry y --ramp-color surface --support-colors 3,1 --block-colors 7",
+        ).unwrap();
+
+        let settings = options.generation_settings();
+        let read_back = Options::from_settings_text(&settings, "test").unwrap();
+
+        assert_eq!(read_back.generation_settings(), settings);
+        assert_eq!(read_back.config.brick_size, (1, 1, 1));
+        assert_eq!(read_back.config.ramp_crest, 2);
+        assert_eq!(read_back.ramp_asset, "PB_MyRamp");
+        assert!(!read_back.floor && read_back.ceiling && !read_back.fill);
+        assert_eq!(read_back.config.color_rule(1), ColorRule::Support);
+        assert_eq!(read_back.config.color_rule(7), ColorRule::Block);
+    }
+
+    #[test]
+    fn presets_pick_their_crest() {
+        assert_eq!(parse("").unwrap().config.ramp_crest, 1);
+        assert_eq!(parse("--preset micro").unwrap().config.ramp_crest, 0);
+        assert_eq!(parse("--preset x4cube").unwrap().config.ramp_crest, 0);
+    }
+
+    #[test]
+    fn settings_skip_comments_and_reject_includes() {
+        let options = Options::from_settings_text("# earlier run\nramp-max-rise = 3 # plates\n\n", "test").unwrap();
+        assert_eq!(options.config.ramp_max_rise, 3);
+
+        let error = Options::from_settings_text("fill = true\nconfig = other.txt", "settings.txt").err().unwrap();
+        assert_eq!(error, "settings.txt:2: config files can't include other config files");
+    }
+
+    #[test]
+    fn strip_previous_is_a_flag() {
+        assert!(!parse("in.brs").unwrap().strip_previous);
+
+        let options = parse("--strip-previous in.brs out.brs").unwrap();
+        assert!(options.strip_previous);
+        assert_eq!((options.in_path.as_str(), options.out_path.as_str()), ("in.brs", "out.brs"));
+
+        assert!(!parse("--strip-previous --no-strip-previous").unwrap().strip_previous);
+        assert!(parse("--strip-previous=maybe").is_err());
+    }
+
+    #[test]
+    fn mark_bricks_is_off_by_default() {
+        assert!(!parse("").unwrap().mark_bricks);
+        assert!(parse("--mark-bricks").unwrap().mark_bricks);
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use brickadia::save::{BrickColor, Color};
+use rampifier::{color::closest_color_index, grid::CompactGrid};
+
+// What to do with bricks using a custom color instead of a palette index.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorMode {
+    // Leave them out of the voxel grid.
+    Skip,
+
+    // Use the closest color in the palette.
+    Nearest,
+
+    // Add up to this many new colors to the palette, then fall back to the closest color.
+    Append(usize),
+}
+
+// Largest palette the voxel grid can index.
+const MAX_PALETTE_LEN: usize = CompactGrid::MAX_VALUE as usize + 1;
+
+pub struct Quantizer {
+    mode: ColorMode,
+
+    // Colors added to the palette so far.
+    added: usize,
+
+    // Palette index each custom color was mapped to.
+    mapped: HashMap<(u8, u8, u8), u8>,
+}
+
+impl Quantizer {
+    pub fn new(mode: ColorMode) -> Self {
+        Self {
+            mode,
+            added: 0,
+            mapped: HashMap::new(),
+        }
+    }
+
+    // Can a brick with this color be given a palette index? Doesn't change the palette.
+    pub fn can_map(&self, color: &BrickColor, palette: &[Color]) -> bool {
+        match color {
+            BrickColor::Index(index) => *index <= CompactGrid::MAX_VALUE as u32,
+            BrickColor::Unique(_) => match self.mode {
+                ColorMode::Skip => false,
+                ColorMode::Nearest => !palette.is_empty(),
+                ColorMode::Append(limit) => limit > 0 || !palette.is_empty(),
+            },
+        }
+    }
+
+    // Returns the palette index to use for a brick's color, adding it to the palette if needed.
+    pub fn palette_index(&mut self, color: &BrickColor, palette: &mut Vec<Color>) -> Option<u8> {
+        if !self.can_map(color, palette) {
+            return None;
+        }
+
+        let color = match color {
+            BrickColor::Index(index) => return Some(*index as u8),
+            BrickColor::Unique(color) => color,
+        };
+
+        let key = (color.r, color.g, color.b);
+        if let Some(&index) = self.mapped.get(&key) {
+            return Some(index);
+        }
+
+        let index = match self.mode {
+            ColorMode::Append(limit) if self.added < limit && palette.len() < MAX_PALETTE_LEN => {
+                match palette.iter().position(|c| (c.r, c.g, c.b) == key) {
+                    Some(index) => index as u8,
+                    None => {
+                        palette.push(Color { a: 255, ..color.clone() });
+                        self.added += 1;
+                        palette.len() as u8 - 1
+                    }
+                }
+            }
+            _ => Self::closest(color, palette)?,
+        };
+
+        self.mapped.insert(key, index);
+        Some(index)
+    }
+
+    // Like `palette_index`, but never adds a color. For bricks that are only looked at, not rebuilt.
+    pub fn existing_index(&self, color: &BrickColor, palette: &[Color]) -> Option<u8> {
+        if !self.can_map(color, palette) {
+            return None;
+        }
+
+        match color {
+            BrickColor::Index(index) => Some(*index as u8),
+            BrickColor::Unique(color) => match self.mapped.get(&(color.r, color.g, color.b)) {
+                Some(&index) => Some(index),
+                None => Self::closest(color, palette),
+            },
+        }
+    }
+
+    fn closest(color: &Color, palette: &[Color]) -> Option<u8> {
+        let palette = &palette[..palette.len().min(MAX_PALETTE_LEN)];
+        if palette.is_empty() {
+            return None;
+        }
+        Some(closest_color_index(color, palette) as u8)
+    }
+
+    pub fn added_colors(&self) -> usize {
+        self.added
+    }
+}
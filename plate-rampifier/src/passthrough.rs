@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use brickadia::save::{Brick, SaveData, User};
+use crate::headers::{find_or_push, find_or_push_owner};
+
+// Copies bricks from `source` into `out` unchanged, remapping their asset, material,
+// owner and component references into `out`'s headers. Bricks using an asset that
+// isn't in `source`'s asset list are skipped. Returns how many bricks were copied.
+pub fn copy_bricks<'a>(out: &mut SaveData, source: &SaveData, bricks: impl IntoIterator<Item = &'a Brick>) -> usize {
+    let assets: Vec<u32> = source.header2.brick_assets
+        .iter()
+        .map(|name| find_or_push(&mut out.header2.brick_assets, name))
+        .collect();
+
+    let materials: Vec<u32> = source.header2.materials
+        .iter()
+        .map(|name| find_or_push(&mut out.header2.materials, name))
+        .collect();
+
+    let physical_materials: Vec<u32> = source.header2.physical_materials
+        .iter()
+        .map(|name| find_or_push(&mut out.header2.physical_materials, name))
+        .collect();
+
+    // Owner indices are 1-based, 0 is PUBLIC.
+    let mut owners: HashMap<u32, u32> = HashMap::new();
+    owners.insert(0, 0);
+
+    let mut count = 0;
+
+    for brick in bricks {
+        let asset_name_index = match assets.get(brick.asset_name_index as usize) {
+            Some(&index) => index,
+            None => continue,
+        };
+
+        let mut brick = brick.clone();
+
+        brick.asset_name_index = asset_name_index;
+        brick.material_index = materials.get(brick.material_index as usize).copied().unwrap_or(0);
+        brick.physical_index = physical_materials.get(brick.physical_index as usize).copied().unwrap_or(0);
+
+        brick.owner_index = *owners.entry(brick.owner_index).or_insert_with(|| {
+            match source.header2.brick_owners.get(brick.owner_index as usize - 1) {
+                Some(owner) => find_or_push_owner(out, &User { name: owner.name.clone(), id: owner.id }),
+                None => 0,
+            }
+        });
+
+        for name in brick.components.keys() {
+            if !out.components.contains_key(name) {
+                if let Some(component) = source.components.get(name) {
+                    out.components.insert(name.clone(), component.clone());
+                }
+            }
+        }
+
+        out.bricks.push(brick);
+        count += 1;
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_bricks_with_unknown_assets() {
+        let mut source = SaveData::default();
+        source.header2.brick_assets = vec!["PB_DefaultBrick".into(), "B_1x1_Round".into()];
+
+        let known = Brick { asset_name_index: 1, ..Brick::default() };
+        let unknown = Brick { asset_name_index: 2, ..Brick::default() };
+
+        let mut out = SaveData::default();
+        out.header2.brick_assets = vec!["B_1x1_Round".into()];
+
+        assert_eq!(copy_bricks(&mut out, &source, [&known, &unknown]), 1);
+        assert_eq!(out.bricks.len(), 1);
+        assert_eq!(out.bricks[0].asset_name_index, 0);
+    }
+}
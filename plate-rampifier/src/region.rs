@@ -0,0 +1,122 @@
+use brickadia::save::{Brick, SaveData};
+
+// Units per stud.
+const STUD: i32 = 10;
+
+// Inclusive minimum and exclusive maximum corners, in units.
+pub type RegionBox = ((i32, i32, i32), (i32, i32, i32));
+
+// Part of the save to rampify. Voxels must be inside one of the boxes and belong
+// to a brick of one of the owners. An empty list doesn't restrict anything.
+#[derive(Clone, Debug, Default)]
+pub struct Region {
+    pub boxes: Vec<RegionBox>,
+
+    // Owner names or ids.
+    pub owners: Vec<String>,
+}
+
+impl Region {
+    // Does this region select the whole save?
+    pub fn is_everything(&self) -> bool {
+        self.boxes.is_empty() && self.owners.is_empty()
+    }
+
+    // Parses a box given as `x1,y1,z1,x2,y2,z2` in studs. The corners can be in any order.
+    pub fn parse_box(value: &str) -> Option<RegionBox> {
+        let parts: Vec<i32> = value
+            .split(',')
+            .map(|part| part.trim().parse::<i32>())
+            .collect::<Result<_, _>>()
+            .ok()?;
+
+        match parts[..] {
+            [x1, y1, z1, x2, y2, z2] => Some((
+                (x1.min(x2) * STUD, y1.min(y2) * STUD, z1.min(z2) * STUD),
+                (x1.max(x2) * STUD, y1.max(y2) * STUD, z1.max(z2) * STUD),
+            )),
+            _ => None,
+        }
+    }
+
+    // Is this point, in units, inside the region's boxes?
+    pub fn contains(&self, pos: (i32, i32, i32)) -> bool {
+        if self.boxes.is_empty() {
+            return true;
+        }
+
+        self.boxes.iter().any(|&(min, max)| {
+            pos.0 >= min.0 && pos.1 >= min.1 && pos.2 >= min.2 &&
+                pos.0 < max.0 && pos.1 < max.1 && pos.2 < max.2
+        })
+    }
+
+    // Is this brick owned by one of the region's owners?
+    pub fn owns(&self, brick: &Brick, save: &SaveData) -> bool {
+        self.owners.is_empty() || is_owned_by(&self.owners, brick, save)
+    }
+}
+
+// Is this brick owned by one of these users, given by name or id?
+pub fn is_owned_by(owners: &[String], brick: &Brick, save: &SaveData) -> bool {
+    // Owner indices are 1-based, 0 is PUBLIC.
+    let owner = match brick.owner_index.checked_sub(1) {
+        Some(index) => match save.header2.brick_owners.get(index as usize) {
+            Some(owner) => owner,
+            None => return false,
+        },
+        None => return false,
+    };
+
+    let id = owner.id.to_string();
+
+    owners.iter().any(|name| name.eq_ignore_ascii_case(&owner.name) || name.eq_ignore_ascii_case(&id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brickadia::save::{BrickOwner, User};
+
+    #[test]
+    fn boxes_are_parsed_in_studs() {
+        assert_eq!(Region::parse_box("4, 0, 2, -1, 3, 1"), Some(((-10, 0, 10), (40, 30, 20))));
+        assert_eq!(Region::parse_box("1,2,3"), None);
+        assert_eq!(Region::parse_box("1,2,3,4,5,x"), None);
+    }
+
+    #[test]
+    fn boxes_include_their_minimum_corner_only() {
+        let region = Region { boxes: vec![((0, 0, 0), (10, 10, 10))], owners: Vec::new() };
+
+        assert!(region.contains((0, 0, 0)));
+        assert!(region.contains((9, 9, 9)));
+        assert!(!region.contains((10, 5, 5)));
+        assert!(!region.contains((5, -1, 5)));
+    }
+
+    #[test]
+    fn empty_region_is_everything() {
+        assert!(Region::default().is_everything());
+        assert!(Region::default().contains((-1000, 5, 1000)));
+        assert!(!Region { boxes: Vec::new(), owners: vec!["Builder".into()] }.is_everything());
+    }
+
+    #[test]
+    fn owners_match_by_name_or_id() {
+        let id = "a8033bee-6c37-4118-b4a6-cecc1d966139";
+
+        let mut save = SaveData::default();
+        save.header2.brick_owners = vec![BrickOwner::from(User { name: "Builder".into(), id: id.parse().unwrap() })];
+
+        let owned = Brick { owner_index: 1, ..Brick::default() };
+        let public = Brick { owner_index: 0, ..Brick::default() };
+
+        assert!(is_owned_by(&["builder".into()], &owned, &save));
+        assert!(is_owned_by(&[id.to_uppercase()], &owned, &save));
+        assert!(!is_owned_by(&["Someone".into()], &owned, &save));
+        assert!(!is_owned_by(&["Builder".into()], &public, &save));
+
+        assert!(Region::default().owns(&public, &save));
+    }
+}
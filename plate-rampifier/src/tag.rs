@@ -0,0 +1,138 @@
+use std::{collections::HashMap, ops::Range};
+use brickadia::save::{Brick, Component, SaveData, UnrealType, User};
+
+// Owner of every generated brick, so later runs can tell them apart from hand-built ones.
+pub fn rampifier_user() -> User {
+    User {
+        name: "rampifier".into(),
+        id: "a8033bee-6c37-4118-b4a6-cecc1d966134".parse().unwrap(),
+    }
+}
+
+// Line the generation settings follow in the save description.
+const SETTINGS_HEADER: &str = "[rampifier]";
+
+// Component added to generated bricks with --mark-bricks. It's not one of Brickadia's
+// own components, the owner is what tells generated bricks apart.
+const MARKER_COMPONENT: &str = "Rampifier_Generated";
+const MARKER_PROPERTY: &str = "bGenerated";
+
+// Was the brick generated by a rampifier run? Bricks are told apart by their owner.
+pub fn is_generated(brick: &Brick, save: &SaveData) -> bool {
+    let id = rampifier_user().id;
+
+    brick.owner_index > 0 &&
+        save.header2.brick_owners
+            .get(brick.owner_index as usize - 1)
+            .is_some_and(|owner| owner.id == id)
+}
+
+/* Appends the settings to a description, replacing any written by an earlier run:
+ *
+ *   This was rampified with rampifier.
+ *
+ *   [rampifier]
+ *   passes = floor,ceiling
+ *   brick-size = 5,5,2
+ *   ...
+ */
+pub fn with_settings(description: &str, settings: &str) -> String {
+    let description = match settings_header(description) {
+        Some((start, _)) => &description[..start],
+        None => description,
+    };
+
+    format!("{}\n\n{}\n{}", description.trim_end(), SETTINGS_HEADER, settings)
+}
+
+// The settings an earlier run wrote into the description, if any.
+pub fn previous_settings(description: &str) -> Option<&str> {
+    settings_header(description).map(|(_, end)| &description[end..])
+}
+
+// Where the header line starts and ends in a description. Only a line holding nothing but the header counts.
+fn settings_header(description: &str) -> Option<(usize, usize)> {
+    let mut start = 0;
+
+    for line in description.split_inclusive('\n') {
+        let end = start + line.len();
+
+        if line.trim() == SETTINGS_HEADER {
+            return Some((start, end));
+        }
+
+        start = end;
+    }
+
+    None
+}
+
+// Adds the marker component to these bricks, and describes it in the save. Returns how many were marked.
+pub fn mark_generated(save: &mut SaveData, bricks: Range<usize>) -> usize {
+    save.components.entry(MARKER_COMPONENT.into()).or_insert_with(|| Component {
+        version: 1,
+        brick_indices: Vec::new(),
+        properties: HashMap::from([(MARKER_PROPERTY.into(), "Boolean".into())]),
+    });
+
+    let mut count = 0;
+
+    for brick in &mut save.bricks[bricks] {
+        brick.components.insert(
+            MARKER_COMPONENT.into(),
+            HashMap::from([(MARKER_PROPERTY.into(), UnrealType::Boolean(true))]),
+        );
+        count += 1;
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brickadia::save::BrickOwner;
+
+    #[test]
+    fn settings_replace_earlier_ones() {
+        let description = with_settings("My build", "fill = true");
+        assert_eq!(description, "My build\n\n[rampifier]\nfill = true");
+        assert_eq!(previous_settings(&description), Some("fill = true"));
+
+        let description = with_settings(&description, "fill = false");
+        assert_eq!(description, "My build\n\n[rampifier]\nfill = false");
+    }
+
+    #[test]
+    fn header_must_be_a_whole_line() {
+        assert_eq!(previous_settings("Made with [rampifier] settings"), None);
+        assert_eq!(previous_settings("Notes\r\n  [rampifier]  \r\nfill = true"), Some("fill = true"));
+        assert_eq!(with_settings("See [rampifier] below", "fill = true"), "See [rampifier] below\n\n[rampifier]\nfill = true");
+    }
+
+    #[test]
+    fn generated_bricks_are_told_apart_by_owner() {
+        let mut save = SaveData::default();
+        save.header2.brick_owners = vec![
+            BrickOwner::from(User { name: "Builder".into(), id: Default::default() }),
+            BrickOwner::from(rampifier_user()),
+        ];
+
+        let owned_by = |owner_index| Brick { owner_index, ..Brick::default() };
+
+        assert!(!is_generated(&owned_by(0), &save));
+        assert!(!is_generated(&owned_by(1), &save));
+        assert!(is_generated(&owned_by(2), &save));
+        assert!(!is_generated(&owned_by(3), &save));
+    }
+
+    #[test]
+    fn marks_only_the_given_bricks() {
+        let mut save = SaveData { bricks: vec![Brick::default(); 3], ..SaveData::default() };
+
+        assert_eq!(mark_generated(&mut save, 1..3), 2);
+        assert!(save.bricks[0].components.is_empty());
+        assert!(save.bricks[1..].iter().all(|brick| brick.components.contains_key(MARKER_COMPONENT)));
+        assert!(save.components.contains_key(MARKER_COMPONENT));
+    }
+}
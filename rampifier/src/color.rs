@@ -0,0 +1,26 @@
+use brickadia::save::Color;
+
+// Distance between two colors in RGB space. Alpha is ignored.
+pub fn color_dist(color1: &Color, color2: &Color) -> f32 {
+    let r = color1.r as f32 - color2.r as f32;
+    let g = color1.g as f32 - color2.g as f32;
+    let b = color1.b as f32 - color2.b as f32;
+
+    ((r * r) + (g * g) + (b * b)).sqrt()
+}
+
+// Index of the palette color closest to `color`. Returns 0 for an empty palette.
+pub fn closest_color_index(color: &Color, colors: &[Color]) -> u32 {
+    let mut closest_index = 0u32;
+    let mut closest_dist = f32::MAX;
+
+    for (i, found_color) in colors.iter().enumerate() {
+        let found_dist = color_dist(color, found_color);
+
+        if closest_dist > found_dist {
+            closest_index = i as u32;
+            closest_dist = found_dist;
+        }
+    }
+    closest_index
+}
@@ -0,0 +1,319 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+use brickadia::save::Brick;
+use crate::{grid::VoxelGrid, Rampifier, RampifierConfig};
+
+type Pos = (usize, usize, usize);
+
+// A group of filled voxels connected through their faces, whatever their values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Component {
+    // Number of voxels.
+    pub voxel_count: usize,
+
+    // Bounding box, as a minimum corner and a size like `VoxelGrid::view`.
+    pub min: Pos,
+    pub size: Pos,
+}
+
+impl Component {
+    // Does it reach the bottom layer of the grid?
+    pub fn touches_ground(&self) -> bool {
+        self.min.2 == 0
+    }
+}
+
+// Connected components of a grid, and which one each voxel belongs to.
+#[derive(Clone, Debug, Default)]
+pub struct Components {
+    // Label of each voxel, laid out like the grid. 0 is empty, otherwise the index into `components` plus one.
+    labels: Vec<u32>,
+
+    components: Vec<Component>,
+}
+
+impl Components {
+    /* Labels the grid's 6-connected components with a flood fill. Components are
+     * numbered in the order their first voxel is found, scanning X, then Y, then Z.
+     */
+    pub fn find(grid: &VoxelGrid) -> Self {
+        let size = grid.size();
+        let mut labels = vec![0u32; grid.len()];
+        let mut components = Vec::new();
+        let mut stack = Vec::new();
+
+        for start in 0..grid.len() {
+            if labels[start] != 0 || !grid.cells().is_filled(start) {
+                continue;
+            }
+
+            let label = components.len() as u32 + 1;
+            let mut min = grid.position(start);
+            let mut max = min;
+            let mut voxel_count = 0;
+
+            labels[start] = label;
+            stack.push(start);
+
+            while let Some(index) = stack.pop() {
+                let pos = grid.position(index);
+                voxel_count += 1;
+
+                min = (min.0.min(pos.0), min.1.min(pos.1), min.2.min(pos.2));
+                max = (max.0.max(pos.0), max.1.max(pos.1), max.2.max(pos.2));
+
+                let (x, y, z) = pos;
+                let neighbors = [
+                    (x > 0).then(|| (x - 1, y, z)),
+                    (x + 1 < size.0).then_some((x + 1, y, z)),
+                    (y > 0).then(|| (x, y - 1, z)),
+                    (y + 1 < size.1).then_some((x, y + 1, z)),
+                    (z > 0).then(|| (x, y, z - 1)),
+                    (z + 1 < size.2).then_some((x, y, z + 1)),
+                ];
+
+                for neighbor in neighbors.into_iter().flatten() {
+                    let neighbor = grid.index(neighbor);
+
+                    if labels[neighbor] == 0 && grid.cells().is_filled(neighbor) {
+                        labels[neighbor] = label;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+
+            components.push(Component {
+                voxel_count,
+                min,
+                size: (max.0 - min.0 + 1, max.1 - min.1 + 1, max.2 - min.2 + 1),
+            });
+        }
+
+        Self { labels, components }
+    }
+
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    // Labels laid out like the grid, see `label`.
+    pub fn labels(&self) -> &[u32] {
+        &self.labels
+    }
+
+    // Index into `components` of the component owning the voxel at this grid index, if it's filled.
+    pub fn label(&self, index: usize) -> Option<usize> {
+        match self.labels[index] {
+            0 => None,
+            label => Some(label as usize - 1),
+        }
+    }
+
+    // Empties the voxels of every component `remove` returns true for. Returns how many voxels were emptied.
+    pub fn remove_where(&self, grid: &mut VoxelGrid, remove: impl Fn(&Component) -> bool) -> usize {
+        let removed: Vec<bool> = self.components.iter().map(remove).collect();
+        let mut count = 0;
+
+        for index in 0..self.labels.len() {
+            if self.label(index).is_some_and(|label| removed[label]) {
+                grid.cells_mut().set(index, None);
+                count += 1;
+            }
+        }
+
+        count
+    }
+}
+
+// Empties components with fewer than `min_voxels` voxels. Returns how many voxels were emptied.
+pub fn remove_small(grid: &mut VoxelGrid, min_voxels: usize) -> usize {
+    Components::find(grid).remove_where(grid, |component| component.voxel_count < min_voxels)
+}
+
+// Empties components that don't reach the bottom layer of the grid. Returns how many voxels were emptied.
+pub fn remove_floating(grid: &mut VoxelGrid) -> usize {
+    Components::find(grid).remove_where(grid, |component| !component.touches_ground())
+}
+
+/* Rampifies each component on its own, spread over all cores, and leaves the grid
+ * as `remove_occupied_voxels` would. Each component is copied into a grid the size
+ * of its bounding box. Voxels of other components inside that box are copied along,
+ * locked, so ramps rest against them but never run through them.
+ *
+ * The ramps can differ from one `Rampifier` over the whole grid: nothing outside
+ * the box is seen, so the air ahead of a ramp is probed differently at its edges,
+ * and ramps near other components fit against locked rather than claimable voxels.
+ *
+ * Bricks are positioned relative to the whole grid, ordered by component.
+ */
+pub fn rampify_components(grid: &mut VoxelGrid, config: &RampifierConfig, floor: bool, ceiling: bool) -> Vec<Brick> {
+    let components = Components::find(grid);
+
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let next = AtomicUsize::new(0);
+
+    // Bricks and rampified box of each component.
+    let mut results: Vec<(usize, Vec<Brick>, VoxelGrid)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(components.len()))
+            .map(|_| scope.spawn(|| {
+                let mut results = Vec::new();
+
+                loop {
+                    let label = next.fetch_add(1, Ordering::Relaxed);
+
+                    let component = match components.components().get(label) {
+                        Some(component) => component,
+                        None => break,
+                    };
+
+                    let (bricks, part) = rampify_component(grid, &components, label, component, config, floor, ceiling);
+                    results.push((label, bricks, part));
+                }
+
+                results
+            }))
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    results.sort_by_key(|(label, _, _)| *label);
+
+    let mut bricks = Vec::new();
+
+    for (label, mut component_bricks, part) in results {
+        let min = components.components()[label].min;
+
+        // Only this component's voxels are written back.
+        for (index, cell) in part.cells().iter().enumerate() {
+            let (x, y, z) = part.position(index);
+            let index = grid.index((min.0 + x, min.1 + y, min.2 + z));
+
+            if components.label(index) == Some(label) {
+                grid.cells_mut().set(index, cell);
+            }
+        }
+
+        bricks.append(&mut component_bricks);
+    }
+
+    bricks
+}
+
+// Rampifies one component in a copy of its bounding box, returning its bricks and the rampified box.
+fn rampify_component(
+    grid: &VoxelGrid,
+    components: &Components,
+    label: usize,
+    component: &Component,
+    config: &RampifierConfig,
+    floor: bool,
+    ceiling: bool,
+) -> (Vec<Brick>, VoxelGrid) {
+    let part = grid.view(component.min, component.size).to_grid();
+
+    let lock_mask: Vec<bool> = (0..part.len())
+        .map(|index| {
+            let (x, y, z) = part.position(index);
+            let pos = (component.min.0 + x, component.min.1 + y, component.min.2 + z);
+
+            components.label(grid.index(pos)).is_some_and(|other| other != label)
+        })
+        .collect();
+
+    let mut rampifier = Rampifier::new(part, config.clone()).with_lock_mask(lock_mask);
+
+    let mut bricks = Vec::new();
+
+    if floor {
+        bricks.append(&mut rampifier.generate_ramps(true));
+    }
+
+    if ceiling {
+        bricks.append(&mut rampifier.generate_ramps(false));
+    }
+
+    rampifier.remove_occupied_voxels();
+
+    // Move the bricks from the box into the grid.
+    let (unit_x, unit_y, unit_z) = (config.brick_size.0 as i32 * 2, config.brick_size.1 as i32 * 2, config.brick_size.2 as i32 * 2);
+
+    for brick in &mut bricks {
+        brick.position.0 += component.min.0 as i32 * unit_x;
+        brick.position.1 += component.min.1 as i32 * unit_y;
+        brick.position.2 += component.min.2 as i32 * unit_z;
+    }
+
+    (bricks, rampifier.move_grid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4x1x1 floor line, a floating pair touching it only by an edge, and a lone voxel.
+    fn islands() -> VoxelGrid {
+        let mut grid = VoxelGrid::new((4, 4, 3));
+
+        for x in 0..4 {
+            grid.set((x, 0, 0), Some(1));
+        }
+
+        grid.set((1, 1, 1), Some(2));
+        grid.set((2, 1, 1), Some(3));
+        grid.set((3, 3, 2), Some(1));
+
+        grid
+    }
+
+    #[test]
+    fn labels_face_connected_voxels() {
+        let grid = islands();
+        let components = Components::find(&grid);
+
+        assert_eq!(components.components(), &[
+            Component { voxel_count: 4, min: (0, 0, 0), size: (4, 1, 1) },
+            Component { voxel_count: 2, min: (1, 1, 1), size: (2, 1, 1) },
+            Component { voxel_count: 1, min: (3, 3, 2), size: (1, 1, 1) },
+        ]);
+
+        assert_eq!(components.label(grid.index((3, 0, 0))), Some(0));
+        assert_eq!(components.label(grid.index((2, 1, 1))), Some(1));
+        assert_eq!(components.label(grid.index((0, 1, 0))), None);
+    }
+
+    #[test]
+    fn remove_floating_keeps_grounded_components() {
+        let mut grid = islands();
+
+        assert_eq!(remove_floating(&mut grid), 3);
+        assert_eq!(Components::find(&grid).components().len(), 1);
+        assert_eq!(grid.get((0, 0, 0)), Some(1));
+        assert_eq!(grid.get((1, 1, 1)), None);
+    }
+
+    #[test]
+    fn remove_small_counts_voxels() {
+        let mut grid = islands();
+
+        assert_eq!(remove_small(&mut grid, 2), 1);
+        assert_eq!(grid.get((3, 3, 2)), None);
+        assert_eq!(grid.get((1, 1, 1)), Some(2));
+
+        assert_eq!(remove_small(&mut grid, 3), 2);
+        assert_eq!(Components::find(&grid).len(), 1);
+    }
+}
@@ -0,0 +1,360 @@
+use brickadia::{
+    save::{Brick, BrickColor, Direction, Rotation, Size},
+    util::get_scale_axis,
+};
+use crate::{
+    grid::{CompactGrid, VoxelGrid},
+    slope_height,
+    Coverage,
+    RampifierConfig,
+    VoxVector,
+};
+
+type Pos = (usize, usize, usize);
+
+// A ramp or wedge brick on the voxel grid, read back from the brick `Rampifier::ramp_brick` made.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RampShape {
+    // Size of the brick's bounds in voxels, along world axes.
+    pub size: Pos,
+
+    // Horizontal direction the slope rises towards, (-1, 0), (1, 0), (0, -1) or (0, 1).
+    pub forward: (isize, isize),
+
+    // Floor ramps rise upwards, ceiling ramps hang downwards.
+    pub is_floor: bool,
+
+    // Length of the flat crest at the high end, in voxels, see `slope_run`.
+    pub crest: usize,
+}
+
+impl RampShape {
+    // Reads the shape of a procedural ramp brick on a grid of `voxel_size` voxels (half extents, like `Size::Procedural`),
+    // for a ramp asset with a `crest` voxels long. Returns None for bricks that don't point up or down, or don't line up with the grid.
+    pub fn from_brick(brick: &Brick, voxel_size: (u32, u32, u32), crest: usize) -> Option<Self> {
+        let size = world_size(brick, voxel_size)?;
+
+        let is_floor = match brick.direction {
+            Direction::ZPositive => true,
+            Direction::ZNegative => false,
+            _ => return None,
+        };
+
+        // Undoes the flip `ramp_brick` does for ceiling ramps.
+        let rotation = match (is_floor, brick.rotation.clone()) {
+            (false, Rotation::Deg0) => Rotation::Deg180,
+            (false, Rotation::Deg180) => Rotation::Deg0,
+            (_, rotation) => rotation,
+        };
+
+        let VoxVector(x, y, _) = VoxVector::forward_vec(rotation);
+
+        Some(Self { size, forward: (x, y), is_floor, crest })
+    }
+
+    // Length and height of the slope, in voxels.
+    pub fn run(&self) -> usize {
+        if self.forward.0 != 0 { self.size.0 } else { self.size.1 }
+    }
+
+    pub fn rise(&self) -> usize {
+        self.size.2
+    }
+
+    // How much of the voxel at this offset from the brick's minimum corner the ramp covers.
+    pub fn coverage(&self, offset: Pos) -> Coverage {
+        // Column counted from the low end of the slope, layer from the ramp's base.
+        let column = match self.forward {
+            (1, _) => offset.0,
+            (-1, _) => self.size.0 - 1 - offset.0,
+            (_, 1) => offset.1,
+            _ => self.size.1 - 1 - offset.1,
+        };
+
+        let layer = if self.is_floor { offset.2 } else { self.rise() - 1 - offset.2 };
+
+        Coverage::of_cell(column, layer, self.run(), self.rise(), self.crest)
+    }
+
+    // Is the voxel at this offset filled? It is if any of it lies under the slope, like the voxels a ramp claims.
+    pub fn contains(&self, offset: Pos) -> bool {
+        self.coverage(offset) != Coverage::Empty
+    }
+
+    // Is this point under the slope, or under the crest? It's measured from the brick's minimum corner, in voxels.
+    pub fn contains_point(&self, point: (f32, f32, f32)) -> bool {
+        let (w, l, _) = self.size;
+
+        // Distance from the low end of the slope.
+        let distance = match self.forward {
+            (1, _) => point.0,
+            (-1, _) => w as f32 - point.0,
+            (_, 1) => point.1,
+            _ => l as f32 - point.1,
+        };
+
+        let height = slope_height(distance, self.run(), self.rise(), self.crest);
+
+        if self.is_floor {
+            point.2 < height
+        }
+        else {
+            point.2 > self.rise() as f32 - height
+        }
+    }
+}
+
+// A brick's size in voxels along world axes, if it lines up with the grid.
+fn world_size(brick: &Brick, voxel_size: (u32, u32, u32)) -> Option<Pos> {
+    let local = match brick.size {
+        Size::Procedural(x, y, z) => [x, y, z],
+        Size::Empty => return None,
+    };
+
+    let world = [
+        local[get_scale_axis(brick, 0) as usize],
+        local[get_scale_axis(brick, 1) as usize],
+        local[get_scale_axis(brick, 2) as usize],
+    ];
+
+    let (vx, vy, vz) = voxel_size;
+    if world[0] % vx != 0 || world[1] % vy != 0 || world[2] % vz != 0 || world.contains(&0) {
+        return None;
+    }
+
+    Some(((world[0] / vx) as usize, (world[1] / vy) as usize, (world[2] / vz) as usize))
+}
+
+// Minimum corner of a brick's bounds, in voxels, given its size in voxels along world axes.
+pub fn min_corner(brick: &Brick, size: Pos, voxel_size: (u32, u32, u32)) -> (i32, i32, i32) {
+    let (x, y, z) = brick.position;
+    let (unit_x, unit_y, unit_z) = (voxel_size.0 as i32 * 2, voxel_size.1 as i32 * 2, voxel_size.2 as i32 * 2);
+
+    (
+        (x - size.0 as i32 * unit_x / 2).div_euclid(unit_x),
+        (y - size.1 as i32 * unit_y / 2).div_euclid(unit_y),
+        (z - size.2 as i32 * unit_z / 2).div_euclid(unit_z),
+    )
+}
+
+// A cube, ramp or wedge made with a config, placed on its voxel grid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PlacedBrick {
+    // None for cubes.
+    pub ramp: Option<RampShape>,
+
+    // Size in voxels along world axes, and minimum corner in voxels.
+    pub size: Pos,
+    pub min: (i32, i32, i32),
+}
+
+impl PlacedBrick {
+    // Reads a brick the config's assets made. Returns None for other assets and bricks that don't line up with the grid.
+    pub fn from_brick(brick: &Brick, config: &RampifierConfig) -> Option<Self> {
+        let voxel_size = config.brick_size;
+        let asset = brick.asset_name_index;
+
+        let ramp = if asset == config.brick_index {
+            None
+        }
+        else if asset == config.ramp_index {
+            Some(RampShape::from_brick(brick, voxel_size, config.ramp_crest)?)
+        }
+        else if asset == config.wedge_index {
+            Some(RampShape::from_brick(brick, voxel_size, 0)?)
+        }
+        else {
+            return None;
+        };
+
+        let size = world_size(brick, voxel_size)?;
+        let min = min_corner(brick, size, voxel_size);
+
+        // The brick's corner has to sit on a voxel corner too.
+        let (unit_x, unit_y, unit_z) = (voxel_size.0 as i32 * 2, voxel_size.1 as i32 * 2, voxel_size.2 as i32 * 2);
+        let corner = (
+            brick.position.0 - size.0 as i32 * unit_x / 2,
+            brick.position.1 - size.1 as i32 * unit_y / 2,
+            brick.position.2 - size.2 as i32 * unit_z / 2,
+        );
+
+        if corner != (min.0 * unit_x, min.1 * unit_y, min.2 * unit_z) {
+            return None;
+        }
+
+        Some(Self { ramp, size, min })
+    }
+
+    // Does any of the voxel at this offset from the minimum corner lie inside the brick?
+    pub fn contains(&self, offset: Pos) -> bool {
+        self.ramp.is_none_or(|ramp| ramp.contains(offset))
+    }
+
+    // Is this point inside the brick? It's measured from the minimum corner, in voxels.
+    pub fn contains_point(&self, point: (f32, f32, f32)) -> bool {
+        self.ramp.is_none_or(|ramp| ramp.contains_point(point))
+    }
+}
+
+// A grid rebuilt from bricks.
+#[derive(Clone, Debug)]
+pub struct Derampified {
+    pub grid: VoxelGrid,
+
+    // Where the grid's first voxel is, in voxels. Multiply by twice the brick size for units.
+    pub min: (i32, i32, i32),
+}
+
+/* Turns cubes, ramps and wedges made with `config` back into voxels, so a rampified
+ * save can be rampified again with different settings. Bricks are told apart by the
+ * config's asset indices, other bricks and bricks without a palette color are skipped.
+ * Ramps fill the voxels under their slope, see `RampShape::contains`.
+ */
+pub fn derampify(bricks: &[Brick], config: &RampifierConfig) -> Derampified {
+    let shapes: Vec<(PlacedBrick, u8)> = bricks
+        .iter()
+        .filter_map(|brick| {
+            let value = match brick.color {
+                BrickColor::Index(index) if index <= CompactGrid::MAX_VALUE as u32 => index as u8,
+                _ => return None,
+            };
+
+            Some((PlacedBrick::from_brick(brick, config)?, value))
+        })
+        .collect();
+
+    let mut min = (i32::MAX, i32::MAX, i32::MAX);
+    let mut max = (i32::MIN, i32::MIN, i32::MIN);
+
+    for &(PlacedBrick { size, min: pos, .. }, _) in &shapes {
+        min = (min.0.min(pos.0), min.1.min(pos.1), min.2.min(pos.2));
+        max = (
+            max.0.max(pos.0 + size.0 as i32),
+            max.1.max(pos.1 + size.1 as i32),
+            max.2.max(pos.2 + size.2 as i32),
+        );
+    }
+
+    if shapes.is_empty() {
+        return Derampified { grid: VoxelGrid::default(), min: (0, 0, 0) };
+    }
+
+    let mut grid = VoxelGrid::new(((max.0 - min.0) as usize, (max.1 - min.1) as usize, (max.2 - min.2) as usize));
+
+    for (placed, value) in shapes {
+        let (size, pos) = (placed.size, placed.min);
+        let pos = ((pos.0 - min.0) as usize, (pos.1 - min.1) as usize, (pos.2 - min.2) as usize);
+
+        for x in 0..size.0 {
+            for y in 0..size.1 {
+                for z in 0..size.2 {
+                    if placed.contains((x, y, z)) {
+                        grid.set((pos.0 + x, pos.1 + y, pos.2 + z), Some(value));
+                    }
+                }
+            }
+        }
+    }
+
+    Derampified { grid, min }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brickadia::save::Color;
+    use crate::{fill::fill_bricks, progress::Hooks, RampDesc, Rampifier};
+
+    // Derampifies a floor ramp rising along +X from the origin, and draws it top layer first.
+    fn derampified_mask(config: RampifierConfig, run: usize, rise: usize) -> Vec<String> {
+        let rampifier = Rampifier::new(VoxelGrid::new((run, 1, rise)), config.clone());
+
+        let desc = RampDesc {
+            position: VoxVector(0, 0, 0),
+            rotation: Rotation::Deg180,
+            is_floor: true,
+            run,
+            rise,
+            crest: config.ramp_crest,
+            color: 3,
+        };
+
+        let rebuilt = derampify(&[rampifier.ramp_brick(&desc)], &config);
+        assert_eq!(rebuilt.min, (0, 0, 0));
+        assert_eq!(rebuilt.grid.size(), (run, 1, rise));
+
+        (0..rise)
+            .rev()
+            .map(|z| {
+                (0..run)
+                    .map(|x| match rebuilt.grid.get((x, 0, z)) {
+                        Some(3) => '#',
+                        Some(_) => '?',
+                        None => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ramp_fills_its_crest() {
+        assert_eq!(derampified_mask(RampifierConfig::default(), 4, 2), [".###", "####"]);
+    }
+
+    #[test]
+    fn micro_wedge_is_all_slope() {
+        assert_eq!(derampified_mask(RampifierConfig::micro(0, 1), 4, 2), ["..##", "####"]);
+    }
+
+    // Steps two voxels wide rising from 1 to 4 voxels, rampified and filled.
+    fn stairs(config: &RampifierConfig) -> (VoxelGrid, Vec<Brick>) {
+        let mut grid = VoxelGrid::new((8, 1, 4));
+        for x in 0..8 {
+            for z in 0..1 + x / 2 {
+                grid.set((x, 0, z), Some(2));
+            }
+        }
+
+        let mut rampifier = Rampifier::new(grid.clone(), config.clone());
+        let mut bricks = rampifier.generate_ramps(true);
+        rampifier.remove_occupied_voxels();
+
+        let mut rest = rampifier.move_grid();
+        bricks.append(&mut fill_bricks(&mut rest, config, &mut Hooks::new()));
+
+        (grid, bricks)
+    }
+
+    #[test]
+    fn rampified_grid_is_covered() {
+        let config = RampifierConfig::default();
+        let (grid, bricks) = stairs(&config);
+        assert!(bricks.iter().any(|brick| brick.asset_name_index != config.brick_index));
+
+        let rebuilt = derampify(&bricks, &config);
+        assert_eq!(rebuilt.min, (0, 0, 0));
+
+        for index in 0..grid.len() {
+            let pos = grid.position(index);
+
+            if grid.get(pos).is_some() {
+                assert_eq!(rebuilt.grid.get(pos), Some(2), "voxel {:?}", pos);
+            }
+        }
+    }
+
+    #[test]
+    fn skips_other_assets_and_custom_colors() {
+        let config = RampifierConfig::default();
+        let (_, bricks) = stairs(&config);
+
+        let mut other = bricks[0].clone();
+        other.asset_name_index = 7;
+
+        let mut custom = bricks[0].clone();
+        custom.color = BrickColor::Unique(Color { r: 1, g: 2, b: 3, a: 255 });
+
+        assert_eq!(derampify(&[other, custom], &config).grid.len(), 0);
+    }
+}
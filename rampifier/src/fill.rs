@@ -0,0 +1,135 @@
+use brickadia::save::{Brick, BrickColor, Size};
+use crate::{
+    grid::GridAccess,
+    progress::{Hooks, Progress, Stage},
+    RampifierConfig,
+};
+
+// Longest side of a filled box, in voxels.
+const MAX_BOX_SIZE: usize = 64;
+
+type Pos = (usize, usize, usize);
+
+// Does the box fit in the grid, with every voxel set to `value`?
+fn can_box<G: GridAccess>(grid: &G, value: u8, pos: Pos, size: Pos) -> bool {
+    let (w, l, h) = size;
+    let grid_size = grid.size();
+
+    if pos.0 + w > grid_size.0 || pos.1 + l > grid_size.1 || pos.2 + h > grid_size.2 {
+        return false;
+    }
+
+    for i in 0..w {
+        for j in 0..l {
+            for k in 0..h {
+                if grid.get((pos.0 + i, pos.1 + j, pos.2 + k)) != Some(value) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    true
+}
+
+fn box_remove<G: GridAccess>(grid: &mut G, pos: Pos, size: Pos) {
+    for i in 0..size.0 {
+        for j in 0..size.1 {
+            for k in 0..size.2 {
+                grid.set((pos.0 + i, pos.1 + j, pos.2 + k), None);
+            }
+        }
+    }
+}
+
+/* Replaces the filled voxels of a grid with as few box bricks as it can find quickly,
+ * growing each box up, then along X, then along Y. The grid is left empty.
+ *
+ * Bricks use the config's brick asset, size and owner, and are positioned relative
+ * to the grid. Checks for cancellation after every layer, and returns the bricks
+ * made so far if cancelled.
+ */
+pub fn fill_bricks<G: GridAccess>(grid: &mut G, config: &RampifierConfig, hooks: &mut Hooks) -> Vec<Brick> {
+    let mut bricks = Vec::new();
+
+    let (grid_w, grid_l, grid_h) = grid.size();
+    let voxel_size = config.brick_size;
+    let (unit_x, unit_y, unit_z) = (voxel_size.0 as i32 * 2, voxel_size.1 as i32 * 2, voxel_size.2 as i32 * 2);
+
+    for z in 0..grid_h {
+        if hooks.is_cancelled() {
+            break;
+        }
+
+        for y in 0..grid_l {
+            for x in 0..grid_w {
+                let val = match grid.get((x, y, z)) {
+                    Some(val) => val,
+                    None => continue,
+                };
+
+                let mut w = 1;
+                let mut l = 1;
+                let mut h = 1;
+
+                // todo: this can be done way better, but this is a shitty quick way to optimize bricks
+                while can_box(grid, val, (x, y, z), (w, l, h)) && h <= MAX_BOX_SIZE {
+                    h += 1;
+                }
+
+                h -= 1;
+
+                if h == 0 {
+                    continue;
+                }
+
+                while can_box(grid, val, (x, y, z), (w, l, h)) && w <= MAX_BOX_SIZE {
+                    w += 1;
+                }
+
+                w -= 1;
+
+                if w == 0 {
+                    continue;
+                }
+
+                while can_box(grid, val, (x, y, z), (w, l, h)) && l <= MAX_BOX_SIZE {
+                    l += 1;
+                }
+
+                l -= 1;
+
+                if l == 0 {
+                    continue;
+                }
+
+                box_remove(grid, (x, y, z), (w, l, h));
+
+                let mut brick = Brick::default();
+
+                let size = (w as u32 * voxel_size.0, l as u32 * voxel_size.1, h as u32 * voxel_size.2);
+                {
+                    let (x, y, z) = (x as i32 * unit_x, y as i32 * unit_y, z as i32 * unit_z);
+
+                    brick.position = (x + size.0 as i32, y + size.1 as i32, z + size.2 as i32);
+                    brick.size = Size::Procedural(size.0, size.1, size.2);
+                }
+
+                brick.asset_name_index = config.brick_index;
+                brick.color = BrickColor::Index(val as u32);
+                brick.owner_index = config.owner_index;
+
+                bricks.push(brick);
+            }
+        }
+
+        hooks.report(Progress {
+            stage: Stage::Fill,
+            done: z + 1,
+            total: grid_h,
+            bricks: bricks.len(),
+        });
+    }
+
+    bricks
+}
@@ -0,0 +1,283 @@
+use crate::grid::VoxelGrid;
+
+/* Clean-up passes for noisy grids, run before handing the grid to `Rampifier`.
+ * Single voxel spikes and pits otherwise turn into tiny ramps.
+ *
+ * Neighbours outside the grid are skipped rather than counted as air, so the
+ * grid's edges aren't eaten away. Voxels a filter adds take the most common value
+ * among their filled neighbours. Every filter returns how many voxels it changed.
+ */
+
+type Pos = (usize, usize, usize);
+
+// Offsets to the 6 face neighbours.
+const FACES: [(isize, isize, isize); 6] = [
+    (-1, 0, 0), (1, 0, 0),
+    (0, -1, 0), (0, 1, 0),
+    (0, 0, -1), (0, 0, 1),
+];
+
+// A filter and how strongly it's applied, see the functions of the same name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    // Steps to erode, then dilate.
+    Open(usize),
+
+    // Steps to dilate, then erode.
+    Close(usize),
+
+    // Least filled neighbours a voxel needs to be kept.
+    RemoveIsolated(usize),
+
+    // Least filled neighbours that fill an empty voxel.
+    FillHoles(usize),
+
+    // Rounds of majority voting.
+    Smooth(usize),
+}
+
+// Runs the filters in order.
+pub fn apply(grid: &mut VoxelGrid, filters: &[Filter]) -> usize {
+    filters
+        .iter()
+        .map(|&filter| match filter {
+            Filter::Open(steps) => open(grid, steps),
+            Filter::Close(steps) => close(grid, steps),
+            Filter::RemoveIsolated(min_neighbors) => remove_isolated(grid, min_neighbors),
+            Filter::FillHoles(min_neighbors) => fill_holes(grid, min_neighbors),
+            Filter::Smooth(rounds) => smooth(grid, rounds),
+        })
+        .sum()
+}
+
+// Values of the neighbours at these offsets that are inside the grid.
+fn neighbors<'a>(
+    grid: &'a VoxelGrid,
+    pos: Pos,
+    offsets: &'a [(isize, isize, isize)],
+) -> impl Iterator<Item = Option<u8>> + 'a {
+    offsets
+        .iter()
+        .map(move |&(x, y, z)| (pos.0 as isize + x, pos.1 as isize + y, pos.2 as isize + z))
+        .filter(|&pos| grid.in_bounds(pos))
+        .map(|pos| grid.get_signed(pos))
+}
+
+// Most common value, the lowest one on ties.
+fn most_common(values: impl Iterator<Item = u8>) -> Option<u8> {
+    let mut counts: Vec<(u8, u32)> = Vec::new();
+
+    for value in values {
+        match counts.iter_mut().find(|(counted, _)| *counted == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|&(value, count)| (count, std::cmp::Reverse(value)))
+        .map(|(value, _)| value)
+}
+
+// Sets every voxel to what `rule` returns for it, reading from a copy of the grid.
+fn rewrite(grid: &mut VoxelGrid, rule: impl Fn(&VoxelGrid, Pos, Option<u8>) -> Option<u8>) -> usize {
+    let before = grid.clone();
+    let mut changed = 0;
+
+    for index in 0..before.len() {
+        let pos = before.position(index);
+        let value = before.cells().get(index);
+        let new_value = rule(&before, pos, value);
+
+        if new_value != value {
+            grid.cells_mut().set(index, new_value);
+            changed += 1;
+        }
+    }
+
+    changed
+}
+
+// Empties filled voxels next to air.
+fn erode(grid: &mut VoxelGrid) -> usize {
+    rewrite(grid, |grid, pos, value| {
+        if value.is_some() && neighbors(grid, pos, &FACES).any(|value| value.is_none()) {
+            None
+        }
+        else {
+            value
+        }
+    })
+}
+
+// Fills empty voxels next to filled ones.
+fn dilate(grid: &mut VoxelGrid) -> usize {
+    rewrite(grid, |grid, pos, value| {
+        value.or_else(|| most_common(neighbors(grid, pos, &FACES).flatten()))
+    })
+}
+
+// Erodes then dilates, removing bumps and spikes up to `steps * 2` voxels thick.
+pub fn open(grid: &mut VoxelGrid, steps: usize) -> usize {
+    (0..steps).map(|_| erode(grid)).sum::<usize>() + (0..steps).map(|_| dilate(grid)).sum::<usize>()
+}
+
+// Dilates then erodes, filling pits and gaps up to `steps * 2` voxels wide.
+pub fn close(grid: &mut VoxelGrid, steps: usize) -> usize {
+    (0..steps).map(|_| dilate(grid)).sum::<usize>() + (0..steps).map(|_| erode(grid)).sum::<usize>()
+}
+
+// Empties filled voxels with fewer than `min_neighbors` filled face neighbours.
+// A `min_neighbors` of 1 removes floating voxels, 2 also removes single voxel spikes.
+pub fn remove_isolated(grid: &mut VoxelGrid, min_neighbors: usize) -> usize {
+    rewrite(grid, |grid, pos, value| {
+        if value.is_some() && neighbors(grid, pos, &FACES).flatten().count() < min_neighbors {
+            None
+        }
+        else {
+            value
+        }
+    })
+}
+
+// Fills empty voxels with at least `min_neighbors` filled face neighbours.
+// A `min_neighbors` of 5 fills single voxel pits, 6 only fills enclosed voxels.
+pub fn fill_holes(grid: &mut VoxelGrid, min_neighbors: usize) -> usize {
+    rewrite(grid, |grid, pos, value| {
+        if value.is_none() && neighbors(grid, pos, &FACES).flatten().count() >= min_neighbors {
+            most_common(neighbors(grid, pos, &FACES).flatten())
+        }
+        else {
+            value
+        }
+    })
+}
+
+/* Majority vote over the 26 surrounding voxels, repeated `rounds` times. A voxel is
+ * filled if most of its neighbours are, emptied if most aren't, and left alone on
+ * a tie. Flat surfaces are kept, corners and edges are rounded off.
+ */
+pub fn smooth(grid: &mut VoxelGrid, rounds: usize) -> usize {
+    let cube: Vec<(isize, isize, isize)> = (-1..=1)
+        .flat_map(|z| (-1..=1).flat_map(move |y| (-1..=1).map(move |x| (x, y, z))))
+        .filter(|&offset| offset != (0, 0, 0))
+        .collect();
+
+    (0..rounds)
+        .map(|_| {
+            rewrite(grid, |grid, pos, value| {
+                let (mut filled, mut total) = (0, 0);
+
+                for neighbor in neighbors(grid, pos, &cube) {
+                    filled += neighbor.is_some() as usize;
+                    total += 1;
+                }
+
+                if filled * 2 > total {
+                    value.or_else(|| most_common(neighbors(grid, pos, &cube).flatten()))
+                }
+                else if filled * 2 < total {
+                    None
+                }
+                else {
+                    value
+                }
+            })
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 5x5 slab two voxels thick, with room above it.
+    fn slab() -> VoxelGrid {
+        let mut grid = VoxelGrid::new((5, 5, 4));
+
+        for x in 0..5 {
+            for y in 0..5 {
+                grid.set((x, y, 0), Some(1));
+                grid.set((x, y, 1), Some(1));
+            }
+        }
+
+        grid
+    }
+
+    fn with(mut grid: VoxelGrid, pos: Pos, value: Option<u8>) -> VoxelGrid {
+        grid.set(pos, value);
+        grid
+    }
+
+    #[test]
+    fn open_removes_thin_parts() {
+        let mut grid = slab();
+        for x in 0..5 {
+            grid.set((x, 2, 3), Some(1));
+        }
+
+        open(&mut grid, 1);
+        assert_eq!(grid, slab());
+    }
+
+    #[test]
+    fn close_fills_narrow_tunnels() {
+        let mut grid = slab();
+        for y in 0..5 {
+            grid.set((2, y, 0), None);
+        }
+
+        close(&mut grid, 1);
+        assert_eq!(grid, slab());
+    }
+
+    #[test]
+    fn remove_isolated_counts_face_neighbours() {
+        let mut grid = with(with(slab(), (2, 2, 2), Some(1)), (0, 0, 3), Some(1));
+
+        // The floating voxel has no neighbours, the spike has one.
+        assert_eq!(remove_isolated(&mut grid, 1), 1);
+        assert_eq!(grid, with(slab(), (2, 2, 2), Some(1)));
+
+        assert_eq!(remove_isolated(&mut grid, 2), 1);
+        assert_eq!(grid, slab());
+    }
+
+    #[test]
+    fn fill_holes_takes_the_most_common_neighbour() {
+        let pitted = with(with(slab(), (2, 2, 1), None), (1, 2, 1), Some(4));
+
+        // Five filled neighbours, four sides and below.
+        let mut grid = pitted.clone();
+        assert_eq!(fill_holes(&mut grid, 6), 0);
+        assert_eq!(fill_holes(&mut grid, 5), 1);
+        assert_eq!(grid.get((2, 2, 1)), Some(1));
+
+        assert_eq!(grid, with(slab(), (1, 2, 1), Some(4)));
+    }
+
+    #[test]
+    fn smooth_keeps_flat_surfaces() {
+        let mut grid = with(slab(), (2, 2, 2), Some(1));
+
+        assert_eq!(smooth(&mut grid, 1), 1);
+        assert_eq!(grid, slab());
+    }
+
+    #[test]
+    fn apply_runs_filters_in_order() {
+        let mut grid = with(with(slab(), (2, 2, 2), Some(1)), (2, 0, 1), None);
+
+        assert_eq!(apply(&mut grid, &[Filter::RemoveIsolated(2), Filter::FillHoles(4)]), 2);
+        assert_eq!(grid, slab());
+    }
+
+    #[test]
+    fn ties_go_to_the_lowest_value() {
+        assert_eq!(most_common([3, 2, 3, 2].into_iter()), Some(2));
+        assert_eq!(most_common([3, 2, 3].into_iter()), Some(3));
+        assert_eq!(most_common(std::iter::empty()), None);
+    }
+}
@@ -0,0 +1,505 @@
+/* Voxel values stored one byte per voxel. `Option<u8>` takes two bytes and can't be
+ * viewed as plain bytes, so empty voxels are stored as a reserved value instead:
+ *
+ *   Option<u8>    None   Some(0)   Some(1)   ...   Some(254)   Some(255)
+ *   byte          255    0         1         ...   254         (not storable)
+ *
+ * Filled voxels keep their value as is, so the bytes can be written out, read back
+ * or memory-mapped without any conversion.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompactGrid {
+    cells: Vec<u8>,
+}
+
+impl CompactGrid {
+    // Byte used for empty voxels.
+    pub const EMPTY: u8 = u8::MAX;
+
+    // Largest value a voxel can hold.
+    pub const MAX_VALUE: u8 = u8::MAX - 1;
+
+    // An empty grid of `len` voxels.
+    pub fn new(len: usize) -> Self {
+        Self { cells: vec![Self::EMPTY; len] }
+    }
+
+    // Uses raw bytes as voxels, `EMPTY` being an empty voxel.
+    pub fn from_bytes(cells: Vec<u8>) -> Self {
+        Self { cells }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.cells
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.cells
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.cells
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<u8> {
+        Self::decode(self.cells[index])
+    }
+
+    pub fn is_filled(&self, index: usize) -> bool {
+        self.cells[index] != Self::EMPTY
+    }
+
+    // Panics if the value is larger than `MAX_VALUE`.
+    pub fn set(&mut self, index: usize, value: Option<u8>) {
+        self.cells[index] = Self::encode(value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Option<u8>> + '_ {
+        self.cells.iter().map(|&cell| Self::decode(cell))
+    }
+
+    // Converts back to one `Option<u8>` per voxel.
+    pub fn to_options(&self) -> Vec<Option<u8>> {
+        self.iter().collect()
+    }
+
+    fn encode(value: Option<u8>) -> u8 {
+        match value {
+            Some(value) => {
+                assert!(value <= Self::MAX_VALUE, "voxel value {} is larger than {}", value, Self::MAX_VALUE);
+                value
+            }
+            None => Self::EMPTY,
+        }
+    }
+
+    fn decode(cell: u8) -> Option<u8> {
+        if cell == Self::EMPTY {
+            None
+        }
+        else {
+            Some(cell)
+        }
+    }
+}
+
+/* Converts one `Option<u8>` per voxel, see the table above.
+ *
+ * # Panics
+ *
+ * If any voxel is `Some(255)`, the byte reserved for empty voxels. Check values
+ * against `CompactGrid::MAX_VALUE` first when they don't come from a palette.
+ */
+impl From<&[Option<u8>]> for CompactGrid {
+    fn from(grid: &[Option<u8>]) -> Self {
+        Self { cells: grid.iter().map(|&value| Self::encode(value)).collect() }
+    }
+}
+
+// Panics like the conversion from a slice.
+impl From<Vec<Option<u8>>> for CompactGrid {
+    fn from(grid: Vec<Option<u8>>) -> Self {
+        Self::from(grid.as_slice())
+    }
+}
+
+impl From<CompactGrid> for Vec<Option<u8>> {
+    fn from(grid: CompactGrid) -> Self {
+        grid.to_options()
+    }
+}
+
+type Pos = (usize, usize, usize);
+
+// A voxel grid and its size. Voxels are laid out X first, then Y, then Z.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VoxelGrid {
+    size: Pos,
+    cells: CompactGrid,
+}
+
+impl VoxelGrid {
+    // An empty grid.
+    pub fn new(size: Pos) -> Self {
+        Self {
+            size,
+            cells: CompactGrid::new(size.0 * size.1 * size.2),
+        }
+    }
+
+    // Takes either a `Vec<Option<u8>>` or a `CompactGrid`, which must hold exactly `size` voxels.
+    // Panics if it doesn't, or if a `Vec<Option<u8>>` holds `Some(255)`, see `CompactGrid`.
+    pub fn from_cells(size: Pos, cells: impl Into<CompactGrid>) -> Self {
+        let cells = cells.into();
+        assert_eq!(cells.len(), size.0 * size.1 * size.2, "grid of size {:?} has the wrong number of voxels", size);
+        Self { size, cells }
+    }
+
+    pub fn size(&self) -> Pos {
+        self.size
+    }
+
+    // Number of voxels, filled or not.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn cells(&self) -> &CompactGrid {
+        &self.cells
+    }
+
+    pub fn cells_mut(&mut self) -> &mut CompactGrid {
+        &mut self.cells
+    }
+
+    pub fn into_cells(self) -> CompactGrid {
+        self.cells
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.cells.as_bytes()
+    }
+
+    // Is this position inside the grid? Takes signed positions so neighbours can be tested without underflowing.
+    pub fn in_bounds(&self, pos: (isize, isize, isize)) -> bool {
+        let (w, l, h) = self.size;
+
+        pos.0 >= 0 && pos.1 >= 0 && pos.2 >= 0 &&
+            pos.0 < w as isize && pos.1 < l as isize && pos.2 < h as isize
+    }
+
+    // Index of a position in the cells. Doesn't check bounds.
+    pub fn index(&self, pos: Pos) -> usize {
+        pos.0 + pos.1 * self.size.0 + pos.2 * self.size.0 * self.size.1
+    }
+
+    // Position of an index in the cells.
+    pub fn position(&self, index: usize) -> Pos {
+        let layer = self.size.0 * self.size.1;
+        (index % self.size.0, index % layer / self.size.0, index / layer)
+    }
+
+    // Value at a position. Positions outside the grid are empty.
+    pub fn get(&self, pos: Pos) -> Option<u8> {
+        if pos.0 < self.size.0 && pos.1 < self.size.1 && pos.2 < self.size.2 {
+            self.cells.get(self.index(pos))
+        }
+        else {
+            None
+        }
+    }
+
+    // Like `get`, for signed positions.
+    pub fn get_signed(&self, pos: (isize, isize, isize)) -> Option<u8> {
+        if self.in_bounds(pos) {
+            self.cells.get(self.index((pos.0 as usize, pos.1 as usize, pos.2 as usize)))
+        }
+        else {
+            None
+        }
+    }
+
+    // Panics if the position is outside the grid.
+    pub fn set(&mut self, pos: Pos, value: Option<u8>) {
+        self.assert_box(pos, (1, 1, 1));
+        let index = self.index(pos);
+        self.cells.set(index, value);
+    }
+
+    // Filled voxels and their values.
+    pub fn iter_filled(&self) -> impl Iterator<Item = (Pos, u8)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, value)| value.map(|value| (self.position(index), value)))
+    }
+
+    // Number of filled voxels.
+    pub fn count_filled(&self) -> usize {
+        self.cells.as_bytes().iter().filter(|&&cell| cell != CompactGrid::EMPTY).count()
+    }
+
+    // Changes the size of the grid. Voxels keep their positions, voxels outside the new size are dropped
+    // and new voxels are empty.
+    pub fn resize(&mut self, size: Pos) {
+        let mut resized = Self::new(size);
+
+        for z in 0..self.size.2.min(size.2) {
+            for y in 0..self.size.1.min(size.1) {
+                let width = self.size.0.min(size.0);
+                let from = self.index((0, y, z));
+                let to = resized.index((0, y, z));
+
+                resized.cells.as_bytes_mut()[to..to + width]
+                    .copy_from_slice(&self.cells.as_bytes()[from..from + width]);
+            }
+        }
+
+        *self = resized;
+    }
+
+    // Sets every voxel in a box. Panics if the box doesn't fit in the grid.
+    pub fn fill_box(&mut self, min: Pos, size: Pos, value: Option<u8>) {
+        self.view_mut(min, size).fill(value);
+    }
+
+    // Is every voxel in this box set to `value`? False if the box doesn't fit in the grid.
+    pub fn is_box_filled_with(&self, min: Pos, size: Pos, value: Option<u8>) -> bool {
+        if min.0 + size.0 > self.size.0 || min.1 + size.1 > self.size.1 || min.2 + size.2 > self.size.2 {
+            return false;
+        }
+
+        self.view(min, size).iter().all(|(_, cell)| cell == value)
+    }
+
+    // Read-only view of a box inside the grid. Panics if the box doesn't fit.
+    pub fn view(&self, min: Pos, size: Pos) -> GridView<'_> {
+        self.assert_box(min, size);
+        GridView { grid: self, min, size }
+    }
+
+    // Mutable view of a box inside the grid. Panics if the box doesn't fit.
+    pub fn view_mut(&mut self, min: Pos, size: Pos) -> GridViewMut<'_> {
+        self.assert_box(min, size);
+        GridViewMut { grid: self, min, size }
+    }
+
+    fn assert_box(&self, min: Pos, size: Pos) {
+        assert!(
+            min.0 + size.0 <= self.size.0 && min.1 + size.1 <= self.size.1 && min.2 + size.2 <= self.size.2,
+            "box at {:?} of size {:?} is outside the grid of size {:?}", min, size, self.size
+        );
+    }
+}
+
+// Positions in a box, X first, in the same order as the grid's voxels.
+pub(crate) fn box_positions(size: Pos) -> impl Iterator<Item = Pos> {
+    (0..size.2).flat_map(move |z| (0..size.1).flat_map(move |y| (0..size.0).map(move |x| (x, y, z))))
+}
+
+// A box inside a `VoxelGrid`. Positions are relative to the box's minimum corner.
+#[derive(Copy, Clone, Debug)]
+pub struct GridView<'a> {
+    grid: &'a VoxelGrid,
+    min: Pos,
+    size: Pos,
+}
+
+impl<'a> GridView<'a> {
+    pub fn size(&self) -> Pos {
+        self.size
+    }
+
+    // Minimum corner of the view in the grid.
+    pub fn min(&self) -> Pos {
+        self.min
+    }
+
+    // Value at a position in the view. Positions outside the view are empty.
+    pub fn get(&self, pos: Pos) -> Option<u8> {
+        if pos.0 < self.size.0 && pos.1 < self.size.1 && pos.2 < self.size.2 {
+            self.grid.get((self.min.0 + pos.0, self.min.1 + pos.1, self.min.2 + pos.2))
+        }
+        else {
+            None
+        }
+    }
+
+    // Every position in the view and its value.
+    pub fn iter(&self) -> impl Iterator<Item = (Pos, Option<u8>)> + 'a {
+        let view = *self;
+        box_positions(self.size).map(move |pos| (pos, view.get(pos)))
+    }
+
+    pub fn iter_filled(&self) -> impl Iterator<Item = (Pos, u8)> + 'a {
+        self.iter().filter_map(|(pos, value)| value.map(|value| (pos, value)))
+    }
+
+    // Copies the view into a grid of its own.
+    pub fn to_grid(&self) -> VoxelGrid {
+        let mut grid = VoxelGrid::new(self.size);
+        for (pos, value) in self.iter_filled() {
+            grid.set(pos, Some(value));
+        }
+        grid
+    }
+}
+
+// A mutable box inside a `VoxelGrid`. Positions are relative to the box's minimum corner.
+#[derive(Debug)]
+pub struct GridViewMut<'a> {
+    grid: &'a mut VoxelGrid,
+    min: Pos,
+    size: Pos,
+}
+
+impl<'a> GridViewMut<'a> {
+    pub fn size(&self) -> Pos {
+        self.size
+    }
+
+    // Minimum corner of the view in the grid.
+    pub fn min(&self) -> Pos {
+        self.min
+    }
+
+    pub fn as_view(&self) -> GridView<'_> {
+        GridView { grid: self.grid, min: self.min, size: self.size }
+    }
+
+    // Value at a position in the view. Positions outside the view are empty.
+    pub fn get(&self, pos: Pos) -> Option<u8> {
+        self.as_view().get(pos)
+    }
+
+    // Panics if the position is outside the view.
+    pub fn set(&mut self, pos: Pos, value: Option<u8>) {
+        assert!(
+            pos.0 < self.size.0 && pos.1 < self.size.1 && pos.2 < self.size.2,
+            "position {:?} is outside the view of size {:?}", pos, self.size
+        );
+        self.grid.set((self.min.0 + pos.0, self.min.1 + pos.1, self.min.2 + pos.2), value);
+    }
+
+    // Sets every voxel in the view.
+    pub fn fill(&mut self, value: Option<u8>) {
+        for pos in box_positions(self.size) {
+            self.set(pos, value);
+        }
+    }
+}
+
+// Storage `Rampifier` can work on. Implemented for owned grids, borrowed grids and
+// views into a larger grid, so parts of a world can be rampified in place.
+pub trait GridAccess {
+    fn size(&self) -> Pos;
+
+    // Value at a position inside the grid.
+    fn get(&self, pos: Pos) -> Option<u8>;
+
+    // Sets a position inside the grid.
+    fn set(&mut self, pos: Pos, value: Option<u8>);
+}
+
+impl GridAccess for VoxelGrid {
+    fn size(&self) -> Pos {
+        self.size
+    }
+
+    #[inline]
+    fn get(&self, pos: Pos) -> Option<u8> {
+        self.cells.get(self.index(pos))
+    }
+
+    #[inline]
+    fn set(&mut self, pos: Pos, value: Option<u8>) {
+        let index = self.index(pos);
+        self.cells.set(index, value);
+    }
+}
+
+impl GridAccess for GridViewMut<'_> {
+    fn size(&self) -> Pos {
+        self.size
+    }
+
+    #[inline]
+    fn get(&self, pos: Pos) -> Option<u8> {
+        let grid = &*self.grid;
+        grid.cells.get(grid.index((self.min.0 + pos.0, self.min.1 + pos.1, self.min.2 + pos.2)))
+    }
+
+    #[inline]
+    fn set(&mut self, pos: Pos, value: Option<u8>) {
+        let index = self.grid.index((self.min.0 + pos.0, self.min.1 + pos.1, self.min.2 + pos.2));
+        self.grid.cells.set(index, value);
+    }
+}
+
+impl<G: GridAccess + ?Sized> GridAccess for &mut G {
+    fn size(&self) -> Pos {
+        (**self).size()
+    }
+
+    #[inline]
+    fn get(&self, pos: Pos) -> Option<u8> {
+        (**self).get(pos)
+    }
+
+    #[inline]
+    fn set(&mut self, pos: Pos, value: Option<u8>) {
+        (**self).set(pos, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_round_trip() {
+        let values = vec![None, Some(0), Some(1), Some(CompactGrid::MAX_VALUE), None];
+        let grid = CompactGrid::from(values.clone());
+
+        assert_eq!(grid.as_bytes(), [255, 0, 1, 254, 255]);
+        assert_eq!(Vec::<Option<u8>>::from(grid), values);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let grid = CompactGrid::from_bytes(vec![CompactGrid::EMPTY, 7, 254]);
+
+        assert_eq!(grid.to_options(), [None, Some(7), Some(254)]);
+        assert!(!grid.is_filled(0));
+        assert_eq!(grid.into_bytes(), [255, 7, 254]);
+    }
+
+    #[test]
+    fn set_largest_value() {
+        let mut grid = CompactGrid::new(2);
+        grid.set(1, Some(254));
+
+        assert_eq!(grid.get(0), None);
+        assert_eq!(grid.get(1), Some(254));
+    }
+
+    #[test]
+    #[should_panic(expected = "voxel value 255 is larger than 254")]
+    fn set_reserved_value() {
+        CompactGrid::new(1).set(0, Some(255));
+    }
+
+    #[test]
+    #[should_panic(expected = "voxel value 255 is larger than 254")]
+    fn from_slice_with_reserved_value() {
+        let _ = CompactGrid::from(&[Some(1), Some(255)][..]);
+    }
+
+    #[test]
+    #[should_panic(expected = "voxel value 255 is larger than 254")]
+    fn from_vec_with_reserved_value() {
+        let _ = CompactGrid::from(vec![Some(255)]);
+    }
+
+    #[test]
+    fn grid_positions() {
+        let mut grid = VoxelGrid::new((3, 2, 2));
+        grid.set((2, 1, 1), Some(4));
+
+        assert_eq!(grid.index((2, 1, 1)), 11);
+        assert_eq!(grid.position(11), (2, 1, 1));
+        assert_eq!(grid.cells().get(11), Some(4));
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use crate::grid::VoxelGrid;
+
+type Pos = (usize, usize, usize);
+
+// What happens to voxels that can't be seen from outside.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HollowMode {
+    // Empties them, leaving a shell.
+    Remove,
+
+    // Gives them all the same value, the most common one among them, so box fill can
+    // cover them with fewer, larger bricks.
+    MergeAcrossColors,
+}
+
+/* Finds the air connected to the outside of the grid through faces. Air sealed off
+ * inside, like a closed cave, doesn't count.
+ */
+fn outside_air(grid: &VoxelGrid) -> Vec<bool> {
+    let (w, l, h) = grid.size();
+    let mut outside = vec![false; grid.len()];
+    let mut queue = VecDeque::new();
+
+    // Start from the empty voxels on the grid's sides.
+    for (index, outside) in outside.iter_mut().enumerate() {
+        let (x, y, z) = grid.position(index);
+        let on_side = x == 0 || y == 0 || z == 0 || x == w - 1 || y == l - 1 || z == h - 1;
+
+        if on_side && !grid.cells().is_filled(index) {
+            *outside = true;
+            queue.push_back(index);
+        }
+    }
+
+    while let Some(index) = queue.pop_front() {
+        let (x, y, z) = grid.position(index);
+
+        let neighbors = [
+            (x > 0).then(|| (x - 1, y, z)),
+            (x + 1 < w).then_some((x + 1, y, z)),
+            (y > 0).then(|| (x, y - 1, z)),
+            (y + 1 < l).then_some((x, y + 1, z)),
+            (z > 0).then(|| (x, y, z - 1)),
+            (z + 1 < h).then_some((x, y, z + 1)),
+        ];
+
+        for neighbor in neighbors.into_iter().flatten() {
+            let neighbor = grid.index(neighbor);
+
+            if !outside[neighbor] && !grid.cells().is_filled(neighbor) {
+                outside[neighbor] = true;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    outside
+}
+
+// Positions around this one, including diagonals, and whether any of them is outside the grid.
+fn surrounding(grid: &VoxelGrid, pos: Pos) -> (impl Iterator<Item = usize> + '_, bool) {
+    let (w, l, h) = grid.size();
+    let (x, y, z) = (pos.0 as isize, pos.1 as isize, pos.2 as isize);
+
+    let on_edge = pos.0 == 0 || pos.1 == 0 || pos.2 == 0 || pos.0 == w - 1 || pos.1 == l - 1 || pos.2 == h - 1;
+
+    let positions = (-1..=1)
+        .flat_map(move |dz| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| (x + dx, y + dy, z + dz))))
+        .filter(move |&pos| pos != (x, y, z) && grid.in_bounds(pos))
+        .map(move |(x, y, z)| grid.index((x as usize, y as usize, z as usize)));
+
+    (positions, on_edge)
+}
+
+/* Finds the filled voxels that can't be seen from outside: those more than `shell`
+ * voxels away from outside air, counting diagonal steps, so slopes and ramps never
+ * open up a view into the inside. The outside of the grid counts as outside air.
+ *
+ *   shell = 1        shell = 2
+ *
+ *   . . . . .        . . . . . . .
+ *   . # # # .        . # # # # # .
+ *   . # - # .        . # # # # # .
+ *   . # # # .        . # # - # # .     # shell   - hidden
+ *   . . . . .        . # # # # # .
+ *                    . # # # # # .
+ *                    . . . . . . .
+ */
+pub fn hidden_voxels(grid: &VoxelGrid, shell: usize) -> Vec<bool> {
+    let outside = outside_air(grid);
+
+    // Steps from outside air, 0 if not reached yet.
+    let mut depth = vec![0usize; grid.len()];
+    let mut queue = VecDeque::new();
+
+    for (index, depth) in depth.iter_mut().enumerate() {
+        if !grid.cells().is_filled(index) {
+            continue;
+        }
+
+        let (mut neighbors, on_edge) = surrounding(grid, grid.position(index));
+
+        if on_edge || neighbors.any(|neighbor| outside[neighbor]) {
+            *depth = 1;
+            queue.push_back(index);
+        }
+    }
+
+    while let Some(index) = queue.pop_front() {
+        if depth[index] >= shell {
+            continue;
+        }
+
+        let (neighbors, _) = surrounding(grid, grid.position(index));
+
+        for neighbor in neighbors {
+            if depth[neighbor] == 0 && grid.cells().is_filled(neighbor) {
+                depth[neighbor] = depth[index] + 1;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    (0..grid.len())
+        .map(|index| grid.cells().is_filled(index) && depth[index] == 0)
+        .collect()
+}
+
+/* Hollows out the grid, keeping a shell `shell` voxels thick (at least 1) around
+ * everything visible from outside. Run it after `Rampifier::remove_occupied_voxels`
+ * and before filling, so voxels under ramps count as visible. Returns how many voxels
+ * were changed.
+ */
+pub fn hollow(grid: &mut VoxelGrid, shell: usize, mode: HollowMode) -> usize {
+    let hidden = hidden_voxels(grid, shell.max(1));
+
+    let value = match mode {
+        HollowMode::Remove => None,
+        HollowMode::MergeAcrossColors => {
+            let mut counts = [0usize; 256];
+
+            for (index, _) in hidden.iter().enumerate().filter(|(_, &hidden)| hidden) {
+                if let Some(value) = grid.cells().get(index) {
+                    counts[value as usize] += 1;
+                }
+            }
+
+            // Most common value, the lowest one on ties.
+            (0..=255u8).rev().max_by_key(|&value| counts[value as usize])
+        }
+    };
+
+    let mut changed = 0;
+
+    for (index, _) in hidden.iter().enumerate().filter(|(_, &hidden)| hidden) {
+        if grid.cells().get(index) != value {
+            grid.cells_mut().set(index, value);
+            changed += 1;
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 5x5x5 cube of `value` with a ring of air around it.
+    fn cube(value: u8) -> VoxelGrid {
+        let mut grid = VoxelGrid::new((7, 7, 7));
+
+        for index in 0..grid.len() {
+            let (x, y, z) = grid.position(index);
+
+            if (1..6).contains(&x) && (1..6).contains(&y) && (1..6).contains(&z) {
+                grid.cells_mut().set(index, Some(value));
+            }
+        }
+
+        grid
+    }
+
+    fn count(hidden: &[bool]) -> usize {
+        hidden.iter().filter(|&&hidden| hidden).count()
+    }
+
+    #[test]
+    fn shell_thickness() {
+        let grid = cube(1);
+
+        assert_eq!(count(&hidden_voxels(&grid, 1)), 27);
+        assert_eq!(count(&hidden_voxels(&grid, 2)), 1);
+        assert_eq!(count(&hidden_voxels(&grid, 3)), 0);
+    }
+
+    #[test]
+    fn sealed_air_is_not_outside() {
+        let mut grid = cube(1);
+        grid.set((3, 3, 3), None);
+
+        assert_eq!(count(&hidden_voxels(&grid, 1)), 26);
+    }
+
+    #[test]
+    fn grid_sides_count_as_outside() {
+        let mut grid = VoxelGrid::new((3, 3, 3));
+        for index in 0..grid.len() {
+            grid.cells_mut().set(index, Some(1));
+        }
+
+        // Only the center is more than one voxel from the outside.
+        assert_eq!(count(&hidden_voxels(&grid, 1)), 1);
+        assert_eq!(count(&hidden_voxels(&grid, 2)), 0);
+    }
+
+    #[test]
+    fn remove_leaves_a_shell() {
+        let mut grid = cube(1);
+
+        assert_eq!(hollow(&mut grid, 1, HollowMode::Remove), 27);
+        assert_eq!(grid.get((2, 2, 2)), None);
+        assert_eq!(grid.get((1, 3, 3)), Some(1));
+    }
+
+    #[test]
+    fn merge_takes_the_most_common_hidden_value() {
+        let mut grid = cube(1);
+        let hidden = hidden_voxels(&grid, 1);
+
+        for (index, _) in hidden.iter().enumerate().filter(|(_, &hidden)| hidden) {
+            grid.cells_mut().set(index, Some(2));
+        }
+        grid.set((3, 3, 3), Some(3));
+
+        // Only the odd one out changes, the shell keeps its color.
+        assert_eq!(hollow(&mut grid, 1, HollowMode::MergeAcrossColors), 1);
+        assert_eq!(grid.get((3, 3, 3)), Some(2));
+        assert_eq!(grid.get((1, 1, 1)), Some(1));
+
+        // A shell at least 1 thick is always kept.
+        assert_eq!(hollow(&mut grid, 0, HollowMode::Remove), 27);
+        assert_eq!(grid.get((1, 1, 1)), Some(1));
+    }
+}
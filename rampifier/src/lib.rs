@@ -1,8 +1,20 @@
-use std::collections::{HashMap, HashSet};
+pub mod color;
+pub mod components;
+pub mod derampify;
+pub mod fill;
+pub mod filters;
+pub mod grid;
+pub mod hollow;
+pub mod progress;
+pub mod validate;
+
+use std::collections::HashMap;
+use grid::{box_positions, GridAccess, VoxelGrid};
+use progress::{Hooks, Progress, Stage};
 use brickadia::save::{Brick, BrickColor, Size, Direction, Rotation};
 use std::{ ops };
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct VoxVector (pub isize, pub isize, pub isize);
 
 impl ops::Add<VoxVector> for VoxVector {
@@ -49,7 +61,7 @@ impl ops::Mul<isize> for VoxVector {
     type Output = VoxVector;
 
     fn mul(self, rhs: isize) -> Self::Output {
-        VoxVector(self.0 * rhs as isize, self.1 * rhs as isize, self.2 * rhs as isize)
+        VoxVector(self.0 * rhs, self.1 * rhs, self.2 * rhs)
     }
 }
 
@@ -68,6 +80,69 @@ impl VoxVector {
     }
 }
 
+// What a voxel value may be used for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorRule {
+    // Can be turned into ramps. This is the default.
+    Ramp,
+
+    // Solid for ramps to rest against, but never turned into a ramp.
+    Support,
+
+    // Never turned into a ramp, and ramps can't run into it or rest against it.
+    Block,
+}
+
+// Which voxels a ramp takes its color from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RampColorSource {
+    // The most common color among all covered voxels.
+    Mode,
+
+    // The most common color among the top-most voxel of each column, so painted surfaces keep their colors.
+    Surface,
+}
+
+// How a voxel picks between directions with the same slope.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TieBreak {
+    // The first direction in the table wins, so -X over +X over -Y over +Y. This is the default.
+    TableOrder,
+
+    // The direction fitting the larger ramp wins, then the one climbing towards the middle of
+    // the grid. Mirrored grids get mirrored ramps, except on the grid's middle lines.
+    Neutral,
+}
+
+// In which order voxels are visited when fitting ramps. Earlier ramps claim voxels first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScanMode {
+    // Along +X, then +Y. This is the default.
+    Single,
+
+    // Runs the pass four times with X and Y scanned forwards and backwards, and keeps the
+    // result covering the most voxels. Takes about four times as long.
+    Mirrored,
+
+    // Fits each layer as a whole, so no voxel is visited first. Together with `TieBreak::Neutral`,
+    // symmetric grids get symmetric ramps. Some overlapping ramps are left out, leaving cubes.
+    Symmetric,
+}
+
+// A mirror plane through the middle of the grid. Ramps are fitted on the low half and mirrored onto the other.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Symmetry {
+    // No mirroring. This is the default.
+    None,
+
+    // Mirrored across the plane halfway along X, so `x` matches `width - 1 - x`.
+    MirrorX,
+
+    // Mirrored across the plane halfway along Y, so `y` matches `length - 1 - y`.
+    MirrorY,
+}
+
+#[derive(Clone, Debug)]
 pub struct RampifierConfig {
     // How wide the ramp can be TODO: This doesn't do anything yet
     pub ramp_max_width: usize,
@@ -99,6 +174,10 @@ pub struct RampifierConfig {
     // Index of the ramp brick. Usually PB_DefaultRamp, but it can be any kind of ramp.
     pub ramp_index: u32,
 
+    // Length of the flat crest at the high end of the ramp brick, in voxels. PB_DefaultRamp
+    // has one a stud long, micro wedges have none. Wedges are all slope either way.
+    pub ramp_crest: usize,
+
     // Index of the ramp brick. Usually PB_DefaultWedge, but it can be any kind of ramp/wedge.
     pub wedge_index: u32,
 
@@ -108,6 +187,24 @@ pub struct RampifierConfig {
 
     // Owner index to use for newly created bricks.
     pub owner_index: u32,
+
+    // Rules for voxel values. Values without a rule can be turned into ramps.
+    pub color_rules: HashMap<u8, ColorRule>,
+
+    // End ramps where the color changes instead of running across it.
+    pub stop_at_color_change: bool,
+
+    // Which voxels the ramp color is picked from.
+    pub ramp_color: RampColorSource,
+
+    // How ties between equally steep directions are broken.
+    pub tie_break: TieBreak,
+
+    // Order voxels are scanned in.
+    pub scan_mode: ScanMode,
+
+    // Mirror plane to keep ramps symmetric across.
+    pub symmetry: Symmetry,
 }
 
 impl Default for RampifierConfig {
@@ -123,14 +220,26 @@ impl Default for RampifierConfig {
             use_wedge: false,
             brick_index: 0,
             ramp_index: 1,
+            ramp_crest: 1,
             wedge_index: 2,
             brick_size: (5, 5, 2),
             owner_index: 1,
+            color_rules: HashMap::new(),
+            stop_at_color_change: false,
+            ramp_color: RampColorSource::Mode,
+            tie_break: TieBreak::TableOrder,
+            scan_mode: ScanMode::Single,
+            symmetry: Symmetry::None,
         }
     }
 }
 
 impl RampifierConfig {
+    pub fn color_rule(&self, value: u8) -> ColorRule {
+        self.color_rules.get(&value).copied().unwrap_or(ColorRule::Ramp)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         ramp_max_width: usize,
         ramp_max_run: usize,
@@ -157,9 +266,16 @@ impl RampifierConfig {
             use_wedge,
             brick_index,
             ramp_index,
+            ramp_crest: 1,
             wedge_index,
             brick_size,
             owner_index,
+            color_rules: HashMap::new(),
+            stop_at_color_change: false,
+            ramp_color: RampColorSource::Mode,
+            tie_break: TieBreak::TableOrder,
+            scan_mode: ScanMode::Single,
+            symmetry: Symmetry::None,
         }
     }
 
@@ -175,9 +291,16 @@ impl RampifierConfig {
             use_wedge: false,
             brick_index: micro_cube_index,
             ramp_index: micro_ramp_index,
+            ramp_crest: 0,
             wedge_index: micro_ramp_index,
             brick_size: (1, 1, 1),
             owner_index: 1,
+            color_rules: HashMap::new(),
+            stop_at_color_change: false,
+            ramp_color: RampColorSource::Mode,
+            tie_break: TieBreak::TableOrder,
+            scan_mode: ScanMode::Single,
+            symmetry: Symmetry::None,
         }
     }
 
@@ -192,55 +315,318 @@ impl RampifierConfig {
             use_wedge: false,
             brick_index: micro_cube_index,
             ramp_index: micro_ramp_index,
+            ramp_crest: 0,
             wedge_index: micro_ramp_index,
             brick_size: (20, 20, 20),
             ramp_max_run: 4,
             owner_index: 1,
+            color_rules: HashMap::new(),
+            stop_at_color_change: false,
+            ramp_color: RampColorSource::Mode,
+            tie_break: TieBreak::TableOrder,
+            scan_mode: ScanMode::Single,
+            symmetry: Symmetry::None,
+        }
+    }
+}
+
+// How much of a cell in a ramp's bounding box the ramp's volume covers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Coverage {
+    // Entirely under the slope. Claimed, and emptied with `remove_occupied_voxels`.
+    Full,
+
+    // The slope runs through it. Claimed and emptied like a full cell, so no cube sticks out of the slope.
+    Partial,
+
+    // Entirely above the slope (below it for ceiling ramps). Left alone, other bricks can fill it.
+    Empty,
+}
+
+impl Coverage {
+    /* Coverage of the cell `column` cells along the slope from its low end, and `layer`
+     * cells up from the ramp's base (down for ceiling ramps). The slope runs straight
+     * from the bottom of the low end to the top of the crest, `crest` columns long, see
+     * `slope_run`. Side view of a ramp with a run of 4, a rise of 3 and a crest of 1:
+     *
+     *   . . / #
+     *   . / # #      # full   / partial   . empty
+     *   / # # #
+     */
+    pub fn of_cell(column: usize, layer: usize, run: usize, rise: usize, crest: usize) -> Self {
+        let slope = slope_run(run, crest);
+
+        if column >= slope {
+            return Coverage::Full;
+        }
+
+        // Compared in units of 1 / slope voxels, where the slope is at `rise` per column.
+        if (layer + 1) * slope <= column * rise {
+            Coverage::Full
+        }
+        else if layer * slope >= (column + 1) * rise {
+            Coverage::Empty
+        }
+        else {
+            Coverage::Partial
+        }
+    }
+}
+
+/* Columns the slope of a ramp or wedge brick spreads over. Ramps may have a flat crest
+ * at the high end, `crest` voxels long. Ramps no longer than their crest, like wedges,
+ * are all slope:
+ *
+ *        __              .
+ *      /|  |            /|
+ *    /  |  |          /  |
+ *   |___|__|         |___|
+ *   crest 1         crest 0
+ */
+pub fn slope_run(run: usize, crest: usize) -> usize {
+    if run > crest { run - crest } else { run }
+}
+
+// Height of a ramp's surface above its base, `distance` voxels along the slope from its low end.
+pub fn slope_height(distance: f32, run: usize, rise: usize, crest: usize) -> f32 {
+    (distance / slope_run(run, crest) as f32).min(1.0) * rise as f32
+}
+
+// A ramp found by `Rampifier::analyze`.
+#[derive(Clone, Debug)]
+pub struct RampDesc {
+    // Voxel the ramp was fitted from, at the low end of its slope.
+    pub position: VoxVector,
+
+    // Direction the slope rises towards, before mirroring ceiling ramps.
+    pub rotation: Rotation,
+
+    // Floor ramps rise upwards, ceiling ramps hang downwards.
+    pub is_floor: bool,
+
+    // Length and height, in voxels.
+    pub run: usize,
+    pub rise: usize,
+
+    // Length of the flat crest at the high end, in voxels, see `slope_run`.
+    pub crest: usize,
+
+    // Voxel value the ramp is colored with.
+    pub color: u8,
+}
+
+impl RampDesc {
+    // Lowest corner of the cells covered by the ramp.
+    pub fn min_corner(&self) -> VoxVector {
+        if self.is_floor {
+            self.position
+        }
+        else {
+            self.position - VoxVector(0, 0, self.rise as isize - 1)
+        }
+    }
+
+    // Grid cells covered by the ramp's bounding box. These may lie outside the grid.
+    pub fn cells(&self) -> impl Iterator<Item = VoxVector> {
+        self.coverage().map(|(pos, _)| pos)
+    }
+
+    // Cells of the bounding box and how much of each the ramp covers.
+    pub fn coverage(&self) -> impl Iterator<Item = (VoxVector, Coverage)> {
+        let (run, rise, crest) = (self.run, self.rise, self.crest);
+        let forward = VoxVector::forward_vec(self.rotation.clone());
+
+        // Layers are counted from the ramp's base, which is at the top for ceilings.
+        let up = if self.is_floor {
+            VoxVector::up()
         }
+        else {
+            VoxVector(0, 0, -1)
+        };
+
+        let pos = self.position;
+
+        (0..run).flat_map(move |i| {
+            (0..rise).map(move |j| (pos + forward * i as isize + up * j as isize, Coverage::of_cell(i, j, run, rise, crest)))
+        })
     }
+
+    // Cells the ramp covers any of, the ones it claims.
+    pub fn covered_cells(&self) -> impl Iterator<Item = VoxVector> {
+        self.coverage()
+            .filter(|&(_, coverage)| coverage != Coverage::Empty)
+            .map(|(pos, _)| pos)
+    }
+}
+
+// A grid cell claimed by a ramp.
+#[derive(Copy, Clone, Debug)]
+pub struct VoxelClaim {
+    // Index into the grid.
+    pub index: usize,
+
+    // Index into `RampAnalysis::ramps`.
+    pub ramp: usize,
 }
 
-pub struct Rampifier {
+// Result of one fitting pass.
+#[derive(Clone, Debug, Default)]
+pub struct RampAnalysis {
+    pub ramps: Vec<RampDesc>,
+    pub claims: Vec<VoxelClaim>,
+}
+
+// Works on any `GridAccess`: an owned `VoxelGrid`, a `&mut VoxelGrid`, or a view
+// into a larger grid from `VoxelGrid::view_mut`. Positions of generated bricks are
+// relative to the grid (or view) it was given.
+pub struct Rampifier<G: GridAccess = VoxelGrid> {
     // Size of this voxel grid.
     size: (usize, usize, usize),
 
     // The Voxel grid, contains a value corresponding to a brick's color id.
-    grid: Vec<Option<u8>>,
+    grid: G,
 
     // Configuration settings to alter how ramps are generated.
     config: RampifierConfig,
 
-    // Grid indices occupied by ramps.
-    ramp_indices: HashSet<usize>
+    // Per-voxel state flags, laid out like the grid. See `VoxelState`.
+    state: Vec<u8>,
+
+    // Length of the run of solid or air voxels starting at each voxel, going up for floor
+    // passes and down for ceiling passes. Rebuilt at the start of every pass.
+    column_runs: Vec<u16>,
+
+    // Progress observer and cancel token.
+    hooks: Hooks,
 }
 
-impl Rampifier {
-    pub fn new(size: (usize, usize, usize), grid: Vec<Option<u8>>, config: RampifierConfig) -> Self {
-        let (w, l, h) = size;
+// Flags stored for each voxel in `Rampifier::state`.
+struct VoxelState;
 
-        Self {
-            size: (w, l, h),
+impl VoxelState {
+    // Occupied by a ramp.
+    const CLAIMED: u8 = 1;
+
+    // Must stay a cube. Ramps can't run through it.
+    const LOCKED: u8 = 1 << 1;
+
+    // Outside the region being rampified. Still solid when fitting ramps.
+    const OUTSIDE: u8 = 1 << 2;
+
+    // Has a value with `ColorRule::Support`.
+    const SUPPORT: u8 = 1 << 3;
+
+    // Has a value with `ColorRule::Block`.
+    const BLOCK: u8 = 1 << 4;
+
+    // Voxels with any of these can't be claimed.
+    const UNCLAIMABLE: u8 = Self::LOCKED | Self::OUTSIDE | Self::SUPPORT | Self::BLOCK;
+}
+
+impl<G: GridAccess> Rampifier<G> {
+    pub fn new(grid: G, config: RampifierConfig) -> Self {
+        let size = grid.size();
+        let state = vec![0; size.0 * size.1 * size.2];
+
+        let mut rampifier = Self {
+            size,
             grid,
             config,
-            ramp_indices: HashSet::new(),
-        }
+            state,
+            column_runs: Vec::new(),
+            hooks: Hooks::new(),
+        };
+
+        rampifier.apply_color_rules();
+        rampifier
     }
 
-    pub fn move_grid(self) -> Vec<Option<u8>> {
+    pub fn move_grid(self) -> G {
         self.grid
     }
 
+    pub fn grid(&self) -> &G {
+        &self.grid
+    }
+
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    // Takes the hooks back out, to reuse them for later work.
+    pub fn take_hooks(&mut self) -> Hooks {
+        std::mem::take(&mut self.hooks)
+    }
+
     pub fn with_config(mut self, config: RampifierConfig) -> Self {
         self.config = config;
+        self.apply_color_rules();
+        self
+    }
+
+    // Stores the config's color rules in the state flags, so they don't need to be looked up for every probe.
+    fn apply_color_rules(&mut self) {
+        const RULE_FLAGS: u8 = VoxelState::SUPPORT | VoxelState::BLOCK;
+
+        for state in self.state.iter_mut() {
+            *state &= !RULE_FLAGS;
+        }
+
+        if self.config.color_rules.is_empty() {
+            return;
+        }
+
+        for (index, pos) in box_positions(self.size).enumerate() {
+            self.state[index] |= match self.grid.get(pos).map(|value| self.config.color_rule(value)) {
+                Some(ColorRule::Support) => VoxelState::SUPPORT,
+                Some(ColorRule::Block) => VoxelState::BLOCK,
+                _ => 0,
+            };
+        }
+    }
+
+    // Limits ramps to the voxels set in `region`, which is laid out like the grid.
+    pub fn with_region(mut self, region: Vec<bool>) -> Self {
+        assert_eq!(region.len(), self.state.len(), "region must be the same size as the grid");
+        self.set_state(&region, false, VoxelState::OUTSIDE);
+        self
+    }
+
+    // Keeps the voxels set in `lock_mask`, which is laid out like the grid, from turning into ramps.
+    // Ramps can't run through locked voxels.
+    pub fn with_lock_mask(mut self, lock_mask: Vec<bool>) -> Self {
+        assert_eq!(lock_mask.len(), self.state.len(), "lock mask must be the same size as the grid");
+        self.set_state(&lock_mask, true, VoxelState::LOCKED);
         self
     }
 
+    // Sets `flag` on voxels where `mask` equals `value`.
+    fn set_state(&mut self, mask: &[bool], value: bool, flag: u8) {
+        for (state, &masked) in self.state.iter_mut().zip(mask) {
+            if masked == value {
+                *state |= flag;
+            }
+            else {
+                *state &= !flag;
+            }
+        }
+    }
+
+    // State flags at this point. Points outside the grid have none.
+    fn state_at(&self, pos: VoxVector) -> u8 {
+        if self.is_bounded(pos) {
+            return self.state[self.grid_index((pos.0 as usize, pos.1 as usize, pos.2 as usize))];
+        }
+        0
+    }
+
     fn grid_index(&self, pos: (usize, usize, usize)) -> usize {
-        pos.0 + pos.1 * self.size.0 as usize + pos.2  * self.size.0 as usize * self.size.1 as usize
+        pos.0 + pos.1 * self.size.0 + pos.2 * self.size.0 * self.size.1
     }
 
     fn get_point(&self, pos: (usize, usize, usize)) -> Option<u8> {
-        self.grid[self.grid_index((pos.0, pos.1, pos.2))]
+        self.grid.get(pos)
     }
 
     // Is this point within the grid?
@@ -260,47 +646,76 @@ impl Rampifier {
     }
 
     fn ramp_exists(&self, pos: VoxVector) -> bool {
-        if self.is_bounded(pos) {
-            let current_index = self.grid_index((pos.0 as usize, pos.1 as usize, pos.2 as usize));
-            return self.ramp_indices.contains(&current_index);
-        }
-        false
+        self.state_at(pos) & VoxelState::CLAIMED != 0
+    }
+
+    // Does the voxel at this point have `ColorRule::Block`?
+    fn is_blocking(&self, pos: VoxVector) -> bool {
+        self.state_at(pos) & VoxelState::BLOCK != 0
+    }
+
+    // Can a ramp claim this point? Air can always be claimed.
+    fn is_claimable(&self, pos: VoxVector) -> bool {
+        !self.vox_exists(pos) || self.state_at(pos) & VoxelState::UNCLAIMABLE == 0
     }
 
     // Returns true if there is a valid value.
     fn vox_exists(&self, pos: VoxVector) -> bool {
-        self.get_point_safe(pos) != None
+        self.get_point_safe(pos).is_some()
     }
 
     fn vox_exists_unsafe(&self, pos: VoxVector) -> bool {
-        self.grid[self.grid_index((pos.0 as usize, pos.1 as usize, pos.2 as usize))] != None
+        self.grid.get((pos.0 as usize, pos.1 as usize, pos.2 as usize)).is_some()
     }
 
-    // Returns change in height from test pt. This only goes upwards, since we scan from the bottom of the world.
-    fn get_slope_from_offset(&self, pos: VoxVector, is_floor: bool) -> i32 {
-        // Invalid state, return none.
-        if !self.vox_exists(pos) {
-            return i32::MIN;
-        }
+    /* Measures the runs of solid and air voxels in every column, so slopes can be
+     * looked up instead of scanned for. Each voxel stores the length of the run it
+     * starts, counting itself, in the direction of the pass:
+     *
+     *   floor (up)   ceiling (down)
+     *
+     *      A  1          A  3
+     *      A  2          A  2
+     *      S  1          S  1
+     *      A  1          A  1
+     *      S  2          S  1
+     *      S  1          S  2
+     *
+     * Runs saturate at u16::MAX.
+     */
+    fn build_column_runs(&mut self, is_floor: bool) {
+        let (w, l, h) = self.size;
+        let layer = w * l;
 
-        let up = if is_floor {
-            VoxVector(0, 0,  1)
-        }
-        else {
-            VoxVector(0, 0, -1)
-        };
+        self.column_runs.clear();
+        self.column_runs.resize(self.state.len(), 1);
+
+        for z in 1..h {
+            // Walk against the pass direction, so the next voxel's run is already known.
+            let (z, next_z) = if is_floor { (h - 1 - z, h - z) } else { (z, z - 1) };
 
-        const MAX_SEARCH: usize = 32;
+            for y in 0..l {
+                for x in 0..w {
+                    let index = x + y * w + z * layer;
+                    let next = x + y * w + next_z * layer;
 
-        for i in 1..MAX_SEARCH {
-            let pos_y = pos + up * i as isize;
-            let done = !self.vox_exists(pos_y);
-            if done {
-                return i as i32;
+                    if self.grid.get((x, y, z)).is_some() == self.grid.get((x, y, next_z)).is_some() {
+                        self.column_runs[index] = self.column_runs[next].saturating_add(1);
+                    }
+                }
             }
         }
+    }
+
+    // Returns change in height from test pt: the number of solid voxels from here up to air,
+    // or down to air for ceilings. Needs `build_column_runs` for the same direction.
+    fn get_slope_from_offset(&self, pos: VoxVector) -> i32 {
+        // Invalid state, return none.
+        if !self.vox_exists(pos) {
+            return i32::MIN;
+        }
 
-        MAX_SEARCH as i32 - 1
+        self.column_runs[self.grid_index((pos.0 as usize, pos.1 as usize, pos.2 as usize))] as i32
     }
 
     // Returns length and height of a ramp.
@@ -316,6 +731,9 @@ impl Rampifier {
         let mut run = 0isize;
         let mut rise = 0isize;
 
+        // Color of the candidate voxel, for stopping at color changes.
+        let color = self.get_point_safe(pos);
+
         // Try increasing the run.
         for _ in 0..self.config.ramp_max_run - 1 {
             // If the vox above is air (or below if ceiling), we continue running.
@@ -326,6 +744,21 @@ impl Rampifier {
                 break;
             }
 
+            let next = pos + (forward * (run + 1));
+
+            if self.is_blocking(next) {
+                return None;
+            }
+
+            // Support voxels, locked voxels and voxels outside the region are left alone.
+            if !self.is_claimable(next) {
+                break;
+            }
+
+            if self.config.stop_at_color_change && self.vox_exists(next) && self.get_point_safe(next) != color {
+                break;
+            }
+
             if has_air && has_vox_forward {
                 run += 1;
             }
@@ -343,6 +776,18 @@ impl Rampifier {
             // Rise until we hit the limit or we find air above (or below if ceiling).
             let pos_air = pos + (up * (rise)) + (forward * (run));
 
+            if self.is_blocking(pos_air) {
+                return None;
+            }
+
+            if !self.is_claimable(pos_air) {
+                break;
+            }
+
+            if self.config.stop_at_color_change && self.vox_exists(pos_air) && self.get_point_safe(pos_air) != color {
+                break;
+            }
+
             if self.vox_exists(pos_air) && !self.ramp_exists(pos_air) {
                 // We've rose too long, this won't be valid.
                 if rise == self.config.ramp_max_rise as isize {
@@ -463,6 +908,11 @@ impl Rampifier {
         let mut has_any_valid = false;
 
         for i in 0..DIR_ROT_HEIGHT_TABLE.len() {
+            // Ramps can't rest against blocking voxels.
+            if self.is_blocking(pos + DIR_ROT_HEIGHT_TABLE[i].0) {
+                continue;
+            }
+
             let pos = pos + DIR_ROT_HEIGHT_TABLE[i].1;
             valid_indexes[i] = !self.vox_exists(pos);
             has_any_valid = valid_indexes[i] || has_any_valid;
@@ -476,10 +926,24 @@ impl Rampifier {
         // If it is not a floor (instead, a ceiling) it will search for air instead of vox.
         for i in 0..valid_indexes.len() {
             if valid_indexes[i] {
-                heights[i] = self.get_slope_from_offset(pos + DIR_ROT_HEIGHT_TABLE[i].0, is_floor);
+                heights[i] = self.get_slope_from_offset(pos + DIR_ROT_HEIGHT_TABLE[i].0);
             }
         }
 
+        if self.config.tie_break == TieBreak::Neutral {
+            let max_height = heights.iter().copied().max().unwrap_or(i32::MIN);
+
+            if max_height <= 0 {
+                return None;
+            }
+
+            let steepest = (0..heights.len())
+                .filter(|&i| heights[i] == max_height)
+                .map(|i| DIR_ROT_HEIGHT_TABLE[i].2.clone());
+
+            return self.neutral_rotation(pos, is_floor, steepest);
+        }
+
         let mut max_height = 0;
         let mut best_rotation: Option<Rotation> = None;
 
@@ -502,6 +966,42 @@ impl Rampifier {
         None
     }
 
+    /* Picks between equally steep directions without favouring any of them, so that
+     * mirroring the grid mirrors the choice:
+     *
+     *  1. The direction fitting the largest ramp (run * rise) wins.
+     *  2. Then the one climbing most towards the middle of the grid, weighed by how far
+     *     the voxel is from the middle along that direction's axis.
+     *  3. Exact ties, only possible on the middle lines, fall back to table order.
+     */
+    fn neutral_rotation(&self, pos: VoxVector, is_floor: bool, candidates: impl Iterator<Item = Rotation>) -> Option<Rotation> {
+        // Position relative to the middle of the grid, doubled to stay whole.
+        let from_middle = VoxVector(
+            pos.0 * 2 - (self.size.0 as isize - 1),
+            pos.1 * 2 - (self.size.1 as isize - 1),
+            0,
+        );
+
+        let mut best: Option<(Rotation, (usize, isize))> = None;
+
+        for rot in candidates {
+            let area = self.fit_ramp(pos, rot.clone(), is_floor)
+                .map_or(0, |(run, rise)| run * rise);
+
+            // Forward points up the slope, so climbing towards the middle means pointing against `from_middle`.
+            let forward = VoxVector::forward_vec(rot.clone());
+            let inwards = -(forward.0 * from_middle.0 + forward.1 * from_middle.1);
+
+            let key = (area, inwards);
+
+            if best.as_ref().is_none_or(|(_, best_key)| key > *best_key) {
+                best = Some((rot, key));
+            }
+        }
+
+        best.map(|(rot, _)| rot)
+    }
+
     /* This offsets the position so that the end of the ramp is where the origin is.
      * O = Origin
      *
@@ -516,8 +1016,10 @@ impl Rampifier {
      * |    |       /
      * |    |    /
      * *----*---
+     *
+     * `unit` is the size of one voxel along X and Y, the origin voxel is included.
      */
-    fn offset_pos(pos: (i32, i32, i32), size: (u32, u32, u32), rot: Rotation) -> (i32, i32, i32) {
+    fn offset_pos(pos: (i32, i32, i32), size: (u32, u32, u32), rot: Rotation, unit: (i32, i32)) -> (i32, i32, i32) {
         let w = size.0 as i32;
         let l = size.1 as i32;
         let h = size.2 as i32;
@@ -526,10 +1028,10 @@ impl Rampifier {
 
         match rot {
             Rotation::Deg0 => {
-                pos = (pos.0 - w + 10, pos.1 + l, pos.2 + h);
+                pos = (pos.0 - w + unit.0, pos.1 + l, pos.2 + h);
             }
             Rotation::Deg90 => {
-                pos = (pos.0 + l, pos.1 - w + 10, pos.2 + h);
+                pos = (pos.0 + l, pos.1 - w + unit.1, pos.2 + h);
             }
             Rotation::Deg180 => {
                 pos = (pos.0 + w, pos.1 + l, pos.2 + h);
@@ -542,80 +1044,108 @@ impl Rampifier {
         pos
     }
 
-    fn create_ramp(
-        &mut self,
-        pos: VoxVector,
-        run: usize,
-        rise: usize,
-        rotation: Rotation,
-        is_floor: bool
-    ) -> Brick {
-        let mut ramp = Brick::default();
-
-        let pos = if is_floor {
-            pos
-        }
-        else {
-            pos - VoxVector(0, 0, rise as isize - 1)
-        };
-
-        let x = pos.0 as i32;
-        let y = pos.1 as i32;
-        let z = pos.2 as i32;
-
-        let brick_w = self.config.brick_size.0;
-        let brick_l = self.config.brick_size.1;
-        let brick_h = self.config.brick_size.2;
-
-        // Select ramp or wedge depending on the size.
-        ramp.asset_name_index = if run < 2 { self.config.wedge_index } else { self.config.ramp_index };
-        ramp.direction = if is_floor { Direction::ZPositive } else { Direction::ZNegative };
-        ramp.rotation = rotation;
-        ramp.owner_index = self.config.owner_index;
+    // Can a ramp claim every voxel it covers? Cells it leaves empty don't matter.
+    fn can_claim(&self, desc: &RampDesc) -> bool {
+        desc.covered_cells().all(|pos| self.is_claimable(pos) && !self.ramp_exists(pos))
+    }
 
-        ramp.position = (x * brick_w as i32 * 2, y * brick_l as i32 * 2, z * brick_h as i32 * 2);
-        {
-            let run = run as u32;
-            let rise = rise as u32;
-            ramp.size = Size::Procedural(run * brick_w, 1 * brick_l, rise * brick_h);
-        }
+    // Crest of a ramp brick with this run. Short ramps become wedges, which have none.
+    fn crest(&self, run: usize) -> usize {
+        if run < 2 { 0 } else { self.config.ramp_crest }
+    }
 
-        if let Size::Procedural(w, l, h) = ramp.size {
-            let size = (w, l, h);
-            ramp.position = Self::offset_pos(ramp.position, size, ramp.rotation.clone());
-        }
+    // Claims the voxels covered by a ramp and picks its color.
+    fn claim_ramp(&mut self, desc_index: usize, desc: RampDesc, claims: &mut Vec<VoxelClaim>) -> RampDesc {
+        let mut desc = desc;
 
         // Add voxel grid indices occupied by this ramp
         let mut mode_values: HashMap<u8, u32> = HashMap::new();
 
-        for i in 0..run as isize {
-            for j in 0..rise as isize {
-                let forward = VoxVector::forward_vec(ramp.rotation.clone());
-                let up = VoxVector::up();
+        for pos in desc.covered_cells() {
+            if self.is_bounded(pos) {
+                let index = self.grid_index((pos.0 as usize, pos.1 as usize, pos.2 as usize));
+                self.state[index] |= VoxelState::CLAIMED;
+                claims.push(VoxelClaim { index, ramp: desc_index });
+                if let Some(value) = self.grid.get((pos.0 as usize, pos.1 as usize, pos.2 as usize)) {
+                    *mode_values.entry(value).or_insert(0) += 1;
+                }
+            }
+        }
 
-                let pos = pos + forward * i as isize + up * j as isize;
+        // Only count the top-most voxel of each column, or bottom-most for ceilings.
+        if self.config.ramp_color == RampColorSource::Surface {
+            let pos = desc.min_corner();
+            let forward = VoxVector::forward_vec(desc.rotation.clone());
+            let mut surface_values: HashMap<u8, u32> = HashMap::new();
+
+            for i in 0..desc.run {
+                let mut column = (0..desc.rise)
+                    .filter(|&j| {
+                        let layer = if desc.is_floor { j } else { desc.rise - 1 - j };
+                        Coverage::of_cell(i, layer, desc.run, desc.rise, desc.crest) != Coverage::Empty
+                    })
+                    .map(|j| pos + forward * i as isize + VoxVector::up() * j as isize);
+
+                let surface = if desc.is_floor {
+                    column.rev().find_map(|pos| self.get_point_safe(pos))
+                }
+                else {
+                    column.find_map(|pos| self.get_point_safe(pos))
+                };
 
-                if self.is_bounded(pos) {
-                    let index = self.grid_index((pos.0 as usize, pos.1 as usize, pos.2 as usize));
-                    self.ramp_indices.insert(index);
-                    if let Some(value) = self.grid[index] {
-                        *mode_values.entry(value).or_insert(0) += 1;
-                    }
+                if let Some(value) = surface {
+                    *surface_values.entry(value).or_insert(0) += 1;
                 }
             }
+
+            if !surface_values.is_empty() {
+                mode_values = surface_values;
+            }
         }
 
         // We're guaranteed a color index if the ramp is being created, so we can unwrap safely.
-        let mode_color = mode_values
+        desc.color = mode_values
             .into_iter()
             .max_by_key(|&(_, count)| count)
             .map(|(val, _)| val)
             .unwrap();
 
-        ramp.color = BrickColor::Index(mode_color as u32);
+        desc
+    }
+
+    // Builds the brick for a ramp found by `analyze`.
+    pub fn ramp_brick(&self, desc: &RampDesc) -> Brick {
+        let mut ramp = Brick::default();
+
+        let VoxVector(x, y, z) = desc.min_corner();
+        let (x, y, z) = (x as i32, y as i32, z as i32);
+
+        let brick_w = self.config.brick_size.0;
+        let brick_l = self.config.brick_size.1;
+        let brick_h = self.config.brick_size.2;
+
+        // Select ramp or wedge depending on the size.
+        ramp.asset_name_index = if desc.run < 2 { self.config.wedge_index } else { self.config.ramp_index };
+        ramp.direction = if desc.is_floor { Direction::ZPositive } else { Direction::ZNegative };
+        ramp.rotation = desc.rotation.clone();
+        ramp.owner_index = self.config.owner_index;
+        ramp.color = BrickColor::Index(desc.color as u32);
+
+        ramp.position = (x * brick_w as i32 * 2, y * brick_l as i32 * 2, z * brick_h as i32 * 2);
+        {
+            let run = desc.run as u32;
+            let rise = desc.rise as u32;
+            ramp.size = Size::Procedural(run * brick_w, brick_l, rise * brick_h);
+        }
+
+        if let Size::Procedural(w, l, h) = ramp.size {
+            let size = (w, l, h);
+            let unit = (brick_w as i32 * 2, brick_l as i32 * 2);
+            ramp.position = Self::offset_pos(ramp.position, size, ramp.rotation.clone(), unit);
+        }
 
         // Needed because changing the direction doesn't mirror the brick on Z.
-        if !is_floor {
+        if !desc.is_floor {
             ramp.rotation = match ramp.rotation {
                 Rotation::Deg0 => Rotation::Deg180,
                 Rotation::Deg180 => Rotation::Deg0,
@@ -626,46 +1156,413 @@ impl Rampifier {
         ramp
     }
 
-    // Process voxel grid and return ramps generated by the algorithm.
-    pub fn generate_ramps(&mut self, gen_floor_else_ceil: bool) -> Vec<Brick> {
-        let mut ramps: Vec<Brick> = Vec::new();
+    // Runs the fitting pass and claims voxels for ramps, without making bricks.
+    // Later passes see the voxels claimed here. Stops early if cancelled.
+    pub fn analyze(&mut self, gen_floor_else_ceil: bool) -> RampAnalysis {
+        self.build_column_runs(gen_floor_else_ceil);
+
+        if self.config.scan_mode == ScanMode::Single {
+            return self.scan(gen_floor_else_ceil, (false, false), (0, 1));
+        }
+
+        // Every scan starts from the same claims, the best one's claims are kept.
+        const FLIPS: [(bool, bool); 4] = [(false, false), (true, false), (false, true), (true, true)];
+
+        let start_state = self.state.clone();
+        let mut best: Option<(RampAnalysis, Vec<u8>)> = None;
+
+        for (pass, &flip) in FLIPS.iter().enumerate() {
+            if self.hooks.is_cancelled() {
+                break;
+            }
 
-        // Estimate amount to reserve, prevent allocations where possible.
-        // Based on testing, ramps count for < ~2% of voxels, but this can vary.
-        let est = ((self.size.0 * self.size.1 * self.size.2) as f32 * 0.02) as usize;
-        ramps.reserve(est);
+            self.state.copy_from_slice(&start_state);
+
+            let analysis = self.scan(gen_floor_else_ceil, flip, (pass, FLIPS.len()));
+
+            // More covered voxels is better, then fewer bricks.
+            let score = |analysis: &RampAnalysis| (analysis.claims.len(), std::cmp::Reverse(analysis.ramps.len()));
+
+            if best.as_ref().is_none_or(|(best, _)| score(&analysis) > score(best)) {
+                best = Some((analysis, self.state.clone()));
+            }
+        }
+
+        match best {
+            Some((analysis, state)) => {
+                self.state = state;
+                analysis
+            }
+            None => RampAnalysis::default(),
+        }
+    }
+
+    // One fitting pass, scanning X and Y backwards if flipped. `pass` is this scan's
+    // index and the number of scans, for progress reports.
+    fn scan(&mut self, gen_floor_else_ceil: bool, flip: (bool, bool), pass: (usize, usize)) -> RampAnalysis {
+        let mut analysis = RampAnalysis::default();
 
         let w = self.size.0 as isize;
         let l = self.size.1 as isize;
         let h = self.size.2 as isize;
 
+        let stage = if gen_floor_else_ceil { Stage::FloorRamps } else { Stage::CeilingRamps };
+
         for z in 0..h {
-            for y in 0..l {
-                for x in 0..w {
-                    let z = if gen_floor_else_ceil { z } else { h - 1 - z };
-
-                    let pos = VoxVector(x, y, z);
-
-                    // Is there a voxel here?
-                    if self.vox_exists_unsafe(pos) && !self.ramp_exists(pos) {
-                        // Is there a candidate for a ramp?
-                        if let Some(rot) = self.best_ramp_rotation(pos, gen_floor_else_ceil) {
-                            if let Some((run, rise)) = self.fit_ramp(pos, rot.clone(), gen_floor_else_ceil) {
-                                let ramp = self.create_ramp(pos, run, rise, rot, gen_floor_else_ceil);
-                                ramps.push(ramp);
-                            }
+            if self.hooks.is_cancelled() {
+                break;
+            }
+
+            let z = if gen_floor_else_ceil { z } else { h - 1 - z };
+
+            if self.config.scan_mode == ScanMode::Symmetric {
+                self.fit_layer(z, gen_floor_else_ceil, &mut analysis);
+            }
+            else if flip == (false, false) && self.config.symmetry == Symmetry::None {
+                self.scan_layer(z, gen_floor_else_ceil, &mut analysis);
+            }
+            else {
+                for y in 0..l {
+                    for x in 0..w {
+                        let y = if flip.1 { l - 1 - y } else { y };
+                        let x = if flip.0 { w - 1 - x } else { x };
+
+                        let pos = VoxVector(x, y, z);
+
+                        if !self.is_fitted_half(pos) {
+                            continue;
+                        }
+
+                        if let Some(desc) = self.fit_at(pos, gen_floor_else_ceil) {
+                            self.place_ramp(desc, &mut analysis);
                         }
                     }
                 }
             }
+
+            let done = if gen_floor_else_ceil { z + 1 } else { h - z };
+
+            self.hooks.report(Progress {
+                stage,
+                done: pass.0 * h as usize + done as usize,
+                total: pass.1 * h as usize,
+                bricks: analysis.ramps.len(),
+            });
+        }
+
+        analysis
+    }
+
+    // Scans a layer along +X, then +Y, without mirroring. This is the default and by far the
+    // most common pass, so it walks the layer by index and skips voxels that can't start a ramp.
+    fn scan_layer(&mut self, z: isize, gen_floor_else_ceil: bool, analysis: &mut RampAnalysis) {
+        let w = self.size.0;
+        let start = z as usize * w * self.size.1;
+
+        for index in start..start + w * self.size.1 {
+            if self.column_runs[index] != 1 {
+                continue;
+            }
+
+            let pos = VoxVector(((index - start) % w) as isize, ((index - start) / w) as isize, z);
+
+            if let Some(desc) = self.fit_at(pos, gen_floor_else_ceil) {
+                let desc = self.claim_ramp(analysis.ramps.len(), desc, &mut analysis.claims);
+                analysis.ramps.push(desc);
+            }
+        }
+    }
+
+    // Are ramps fitted from this voxel? With a mirror plane only the low half is fitted, including the middle.
+    fn is_fitted_half(&self, pos: VoxVector) -> bool {
+        match self.config.symmetry {
+            Symmetry::None => true,
+            Symmetry::MirrorX => pos.0 * 2 < self.size.0 as isize,
+            Symmetry::MirrorY => pos.1 * 2 < self.size.1 as isize,
+        }
+    }
+
+    // The ramp mirrored across the symmetry plane.
+    fn mirror_ramp(&self, desc: &RampDesc) -> RampDesc {
+        let VoxVector(x, y, z) = desc.position;
+        let (w, l) = (self.size.0 as isize, self.size.1 as isize);
+
+        let (position, rotation) = match (self.config.symmetry, desc.rotation.clone()) {
+            (Symmetry::MirrorX, Rotation::Deg0) => (VoxVector(w - 1 - x, y, z), Rotation::Deg180),
+            (Symmetry::MirrorX, Rotation::Deg180) => (VoxVector(w - 1 - x, y, z), Rotation::Deg0),
+            (Symmetry::MirrorX, rotation) => (VoxVector(w - 1 - x, y, z), rotation),
+            (Symmetry::MirrorY, Rotation::Deg90) => (VoxVector(x, l - 1 - y, z), Rotation::Deg270),
+            (Symmetry::MirrorY, Rotation::Deg270) => (VoxVector(x, l - 1 - y, z), Rotation::Deg90),
+            (Symmetry::MirrorY, rotation) => (VoxVector(x, l - 1 - y, z), rotation),
+            (Symmetry::None, rotation) => (desc.position, rotation),
+        };
+
+        RampDesc { position, rotation, ..desc.clone() }
+    }
+
+    /* Claims a fitted ramp, and its mirror image if there's a symmetry plane. Both are
+     * left out unless the mirror fits the same way on the other half, so the halves
+     * always match. Ramps running along the plane, from its middle voxel, are their own
+     * mirror image. Any other ramp touching the plane overlaps its mirror and is left
+     * out, even one covering the same cells, as the two slope in opposite directions.
+     */
+    fn place_ramp(&mut self, desc: RampDesc, analysis: &mut RampAnalysis) {
+        let mirror = match self.config.symmetry {
+            Symmetry::None => None,
+            _ => {
+                let mirror = self.mirror_ramp(&desc);
+                let cells: Vec<VoxVector> = desc.covered_cells().collect();
+
+                if mirror.position == desc.position && mirror.rotation == desc.rotation {
+                    None
+                }
+                else if mirror.covered_cells().any(|pos| cells.contains(&pos)) || !self.mirror_fits(&mirror) {
+                    return;
+                }
+                else {
+                    Some(mirror)
+                }
+            }
+        };
+
+        for desc in std::iter::once(desc).chain(mirror) {
+            let desc = self.claim_ramp(analysis.ramps.len(), desc, &mut analysis.claims);
+            analysis.ramps.push(desc);
+        }
+    }
+
+    // Would a ramp fitted at the mirrored voxel, in the mirrored direction, come out the same?
+    fn mirror_fits(&self, mirror: &RampDesc) -> bool {
+        let pos = mirror.position;
+
+        if !self.is_bounded(pos) {
+            return false;
+        }
+
+        let index = self.grid_index((pos.0 as usize, pos.1 as usize, pos.2 as usize));
+
+        self.column_runs[index] == 1 &&
+            self.vox_exists_unsafe(pos) &&
+            !self.ramp_exists(pos) &&
+            self.fit_ramp(pos, mirror.rotation.clone(), mirror.is_floor) == Some((mirror.run, mirror.rise)) &&
+            self.can_claim(mirror)
+    }
+
+    // Fits a ramp starting at this voxel, if it can have one. The ramp isn't claimed or colored yet.
+    fn fit_at(&self, pos: VoxVector, gen_floor_else_ceil: bool) -> Option<RampDesc> {
+        // Only surface voxels can be ramps. A voxel with air above it (or below
+        // if ceiling) starts a run of one solid voxel.
+        let index = self.grid_index((pos.0 as usize, pos.1 as usize, pos.2 as usize));
+        if self.column_runs[index] != 1 {
+            return None;
+        }
+
+        // Is there a voxel here?
+        if !self.vox_exists_unsafe(pos) || self.ramp_exists(pos) || !self.is_claimable(pos) {
+            return None;
+        }
+
+        // Is there a candidate for a ramp?
+        let rot = self.best_ramp_rotation(pos, gen_floor_else_ceil)?;
+        let (run, rise) = self.fit_ramp(pos, rot.clone(), gen_floor_else_ceil)?;
+
+        let desc = RampDesc {
+            position: pos,
+            rotation: rot,
+            is_floor: gen_floor_else_ceil,
+            run,
+            rise,
+            crest: self.crest(run),
+            color: 0,
+        };
+
+        if !self.can_claim(&desc) {
+            return None;
+        }
+
+        Some(desc)
+    }
+
+    /* Fits a whole layer at once for `ScanMode::Symmetric`. Ramps are fitted against the
+     * claims so far, then claimed largest first. Equally large ramps that overlap each
+     * other are both dropped, so the scan order never picks a winner. Voxels that lost
+     * out are fitted again against the new claims, until nothing more is claimed.
+     */
+    fn fit_layer(&mut self, z: isize, gen_floor_else_ceil: bool, analysis: &mut RampAnalysis) {
+        let (w, l) = (self.size.0 as isize, self.size.1 as isize);
+
+        // Voxels to fit, the whole layer at first and then the ones that lost out.
+        let mut positions: Vec<VoxVector> = (0..l)
+            .flat_map(|y| (0..w).map(move |x| VoxVector(x, y, z)))
+            .filter(|&pos| self.is_fitted_half(pos))
+            .collect();
+
+        // How many ramps of the current size cover each cell.
+        let mut covered: HashMap<VoxVector, u32> = HashMap::new();
+
+        loop {
+            let mut candidates: Vec<RampDesc> = positions
+                .iter()
+                .filter_map(|&pos| self.fit_at(pos, gen_floor_else_ceil))
+                .collect();
+
+            candidates.sort_by_key(|desc| std::cmp::Reverse(desc.run * desc.rise));
+
+            let claimed = analysis.ramps.len();
+
+            for group in candidates.chunk_by(|a, b| a.run * a.rise == b.run * b.rise) {
+                // Ramps running into a larger one's cells are out.
+                let free: Vec<&RampDesc> = group
+                    .iter()
+                    .filter(|desc| desc.covered_cells().all(|pos| !self.ramp_exists(pos)))
+                    .collect();
+
+                covered.clear();
+
+                for pos in free.iter().flat_map(|desc| desc.covered_cells()) {
+                    *covered.entry(pos).or_insert(0) += 1;
+                }
+
+                for &desc in &free {
+                    if desc.covered_cells().all(|pos| covered[&pos] == 1) {
+                        self.place_ramp(desc.clone(), analysis);
+                    }
+                }
+            }
+
+            if analysis.ramps.len() == claimed {
+                break;
+            }
+
+            positions = candidates
+                .iter()
+                .map(|desc| desc.position)
+                .filter(|&pos| !self.ramp_exists(pos))
+                .collect();
         }
+    }
+
+    // Process voxel grid and return ramps generated by the algorithm. Stops early if cancelled,
+    // returning the ramps found so far.
+    pub fn generate_ramps(&mut self, gen_floor_else_ceil: bool) -> Vec<Brick> {
+        let analysis = self.analyze(gen_floor_else_ceil);
 
-        ramps
+        analysis.ramps
+            .iter()
+            .map(|desc| self.ramp_brick(desc))
+            .collect()
     }
 
     pub fn remove_occupied_voxels(&mut self) {
-        for &index in &self.ramp_indices {
-            self.grid[index] = None;
+        for (index, &state) in self.state.iter().enumerate() {
+            // Locked voxels are never cleared.
+            if state & (VoxelState::CLAIMED | VoxelState::LOCKED) == VoxelState::CLAIMED {
+                let layer = self.size.0 * self.size.1;
+                let pos = (index % self.size.0, index % layer / self.size.0, index / layer);
+                self.grid.set(pos, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Steps one voxel high every two voxels along X, which fit a ramp on every step.
+    fn stairs() -> VoxelGrid {
+        let size = (8, 1, 4);
+        let mut grid = VoxelGrid::new(size);
+
+        for x in 0..size.0 {
+            for z in 0..1 + x / 2 {
+                grid.set((x, 0, z), Some(0));
+            }
         }
+
+        grid
     }
-}
\ No newline at end of file
+
+    fn claimed(grid: &VoxelGrid, analysis: &RampAnalysis) -> Vec<(usize, usize, usize)> {
+        analysis.claims.iter().map(|claim| grid.position(claim.index)).collect()
+    }
+
+    #[test]
+    fn claims_stay_inside_the_region() {
+        let grid = stairs();
+        let region: Vec<bool> = (0..grid.len()).map(|index| grid.position(index).0 < 4).collect();
+
+        let mut rampifier = Rampifier::new(grid.clone(), RampifierConfig::default()).with_region(region);
+        let analysis = rampifier.analyze(true);
+
+        assert!(!analysis.ramps.is_empty());
+        assert!(claimed(&grid, &analysis).iter().all(|pos| pos.0 < 4));
+
+        // Everything outside the region is left in the grid.
+        rampifier.remove_occupied_voxels();
+        let after = rampifier.move_grid();
+
+        for index in (0..grid.len()).filter(|&index| grid.position(index).0 >= 4) {
+            assert_eq!(after.cells().get(index), grid.cells().get(index));
+        }
+    }
+
+    #[test]
+    fn locked_voxels_are_never_claimed() {
+        let grid = stairs();
+        let locked: Vec<bool> = (0..grid.len()).map(|index| grid.position(index).0 == 5).collect();
+
+        let mut rampifier = Rampifier::new(grid.clone(), RampifierConfig::default()).with_lock_mask(locked);
+        let analysis = rampifier.analyze(true);
+
+        assert!(!analysis.ramps.is_empty());
+        assert!(claimed(&grid, &analysis).iter().all(|pos| pos.0 != 5));
+
+        rampifier.remove_occupied_voxels();
+        let after = rampifier.move_grid();
+
+        for index in (0..grid.len()).filter(|&index| grid.position(index).0 == 5) {
+            assert_eq!(after.cells().get(index), grid.cells().get(index));
+        }
+    }
+
+    // Draws `Coverage::of_cell` for a ramp top layer first, like the diagram on it.
+    fn coverage_mask(run: usize, rise: usize, crest: usize) -> Vec<String> {
+        (0..rise)
+            .rev()
+            .map(|layer| {
+                (0..run)
+                    .map(|column| match Coverage::of_cell(column, layer, run, rise, crest) {
+                        Coverage::Full => '#',
+                        Coverage::Partial => '/',
+                        Coverage::Empty => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn coverage_of_a_ramp_with_a_crest() {
+        assert_eq!(coverage_mask(4, 3, 1), ["../#", "./##", "/###"]);
+        assert_eq!(coverage_mask(4, 2, 1), [".//#", "//##"]);
+    }
+
+    #[test]
+    fn coverage_of_a_wedge() {
+        assert_eq!(coverage_mask(4, 2, 0), ["..//", "//##"]);
+        assert_eq!(coverage_mask(2, 4, 0), ["./", "./", "/#", "/#"]);
+    }
+
+    #[test]
+    fn slope_run_leaves_short_ramps_alone() {
+        assert_eq!(slope_run(4, 1), 3);
+        assert_eq!(slope_run(4, 0), 4);
+        assert_eq!(slope_run(1, 1), 1);
+        assert_eq!(slope_run(2, 3), 2);
+
+        assert_eq!(slope_height(1.5, 4, 3, 1), 1.5);
+        assert_eq!(slope_height(3.5, 4, 3, 1), 3.0);
+        assert_eq!(slope_height(2.0, 4, 2, 0), 1.0);
+    }
+}
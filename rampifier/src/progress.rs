@@ -0,0 +1,103 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+// Which part of the work a `Progress` report is about.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stage {
+    // Turning bricks into voxels.
+    Voxelize,
+
+    FloorRamps,
+    CeilingRamps,
+
+    // Filling the remaining voxels with bricks.
+    Fill,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Progress {
+    pub stage: Stage,
+
+    // Units of work done and to do in this stage, usually grid layers.
+    pub done: usize,
+    pub total: usize,
+
+    // Bricks made so far in this stage.
+    pub bricks: usize,
+}
+
+impl Progress {
+    // How much of this stage is done, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        }
+        else {
+            self.done as f32 / self.total as f32
+        }
+    }
+}
+
+// Stops work from another thread. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+type Observer = Box<dyn FnMut(&Progress) + Send>;
+
+/* Progress observer and cancel token, passed to long running work. Both are optional:
+ *
+ *   let token = CancelToken::new();
+ *   let hooks = Hooks::new()
+ *       .with_observer(|progress| println!("{:?} {:.0}%", progress.stage, progress.fraction() * 100.0))
+ *       .with_cancel_token(token.clone());
+ *
+ * Work checks the token regularly and stops early once it's cancelled, returning
+ * what it made so far.
+ */
+#[derive(Default)]
+pub struct Hooks {
+    observer: Option<Observer>,
+    cancel: Option<CancelToken>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_observer(mut self, observer: impl FnMut(&Progress) + Send + 'static) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub fn report(&mut self, progress: Progress) {
+        if let Some(observer) = &mut self.observer {
+            observer(&progress);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|token| token.is_cancelled())
+    }
+}
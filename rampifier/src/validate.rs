@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use brickadia::save::Brick;
+use crate::{derampify::PlacedBrick, grid::VoxelGrid, RampifierConfig};
+
+type Pos = (usize, usize, usize);
+
+// Samples per voxel along each axis when measuring how much two bricks overlap.
+pub const SAMPLES: usize = 4;
+
+// Two bricks sharing some of their volume.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Overlap {
+    // Indices into the brick list, `first` < `second`.
+    pub first: usize,
+    pub second: usize,
+
+    // One of the voxels they share, and how much they share in total, in voxels.
+    pub voxel: Pos,
+    pub volume: f32,
+}
+
+// Problems `validate` found.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub overlaps: Vec<Overlap>,
+
+    // Voxels filled in the expected grid that no brick covers any of.
+    pub gaps: Vec<Pos>,
+
+    // Bricks reaching outside the grid, by index.
+    pub out_of_grid: Vec<usize>,
+
+    // Bricks that weren't checked: other assets, or not lined up with the grid.
+    pub skipped: Vec<usize>,
+}
+
+impl Report {
+    // No overlaps, gaps or bricks outside the grid. Skipped bricks don't count.
+    pub fn is_clean(&self) -> bool {
+        self.overlaps.is_empty() && self.gaps.is_empty() && self.out_of_grid.is_empty()
+    }
+}
+
+// Volume the two bricks share inside one voxel, in samples.
+fn shared_samples(a: &PlacedBrick, b: &PlacedBrick, voxel: Pos) -> usize {
+    let step = 1.0 / SAMPLES as f32;
+    let mut shared = 0;
+
+    for i in 0..SAMPLES {
+        for j in 0..SAMPLES {
+            for k in 0..SAMPLES {
+                let point = (
+                    voxel.0 as f32 + (i as f32 + 0.5) * step,
+                    voxel.1 as f32 + (j as f32 + 0.5) * step,
+                    voxel.2 as f32 + (k as f32 + 0.5) * step,
+                );
+
+                let inside = |brick: &PlacedBrick| brick.contains_point((
+                    point.0 - brick.min.0 as f32,
+                    point.1 - brick.min.1 as f32,
+                    point.2 - brick.min.2 as f32,
+                ));
+
+                if inside(a) && inside(b) {
+                    shared += 1;
+                }
+            }
+        }
+    }
+
+    shared
+}
+
+/* Checks generated bricks against the grid they were made from. Bricks are placed
+ * like `derampify` reads them, positioned relative to the grid, with ramps cut along
+ * their slope. Where two bricks touch the same voxel, it's sampled `SAMPLES` times
+ * along each axis to find out whether they really overlap, so a floor and a ceiling
+ * ramp meeting along their slopes don't count:
+ *
+ *    _______
+ *   |      /|
+ *   |  C  / |    C ceiling ramp
+ *   |   /   |    F floor ramp
+ *   | /  F  |
+ *   |/______|
+ */
+pub fn validate(bricks: &[Brick], config: &RampifierConfig, expected: &VoxelGrid) -> Report {
+    let mut report = Report::default();
+
+    let placed: Vec<Option<PlacedBrick>> = bricks
+        .iter()
+        .map(|brick| PlacedBrick::from_brick(brick, config))
+        .collect();
+
+    // First brick touching each voxel, plus one, and any others after it.
+    let mut first = vec![0u32; expected.len()];
+    let mut shared: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    for (index, brick) in placed.iter().enumerate() {
+        let brick = match brick {
+            Some(brick) => brick,
+            None => {
+                report.skipped.push(index);
+                continue;
+            }
+        };
+
+        let mut outside = false;
+
+        for x in 0..brick.size.0 {
+            for y in 0..brick.size.1 {
+                for z in 0..brick.size.2 {
+                    if !brick.contains((x, y, z)) {
+                        continue;
+                    }
+
+                    let pos = (
+                        (brick.min.0 + x as i32) as isize,
+                        (brick.min.1 + y as i32) as isize,
+                        (brick.min.2 + z as i32) as isize,
+                    );
+
+                    if !expected.in_bounds(pos) {
+                        outside = true;
+                        continue;
+                    }
+
+                    let voxel = expected.index((pos.0 as usize, pos.1 as usize, pos.2 as usize));
+
+                    if first[voxel] == 0 {
+                        first[voxel] = index as u32 + 1;
+                    }
+                    else {
+                        shared.entry(voxel).or_default().push(index);
+                    }
+                }
+            }
+        }
+
+        if outside {
+            report.out_of_grid.push(index);
+        }
+    }
+
+    // Samples shared by each pair of bricks, and one voxel they share.
+    let mut pairs: HashMap<(usize, usize), (Pos, usize)> = HashMap::new();
+
+    for (&voxel, others) in &shared {
+        let pos = expected.position(voxel);
+        let touching: Vec<usize> = std::iter::once(first[voxel] as usize - 1).chain(others.iter().copied()).collect();
+
+        for (i, &a) in touching.iter().enumerate() {
+            for &b in &touching[i + 1..] {
+                let (a, b) = (a.min(b), a.max(b));
+                let samples = shared_samples(placed[a].as_ref().unwrap(), placed[b].as_ref().unwrap(), pos);
+
+                if samples > 0 {
+                    let entry = pairs.entry((a, b)).or_insert((pos, 0));
+                    entry.0 = entry.0.min(pos);
+                    entry.1 += samples;
+                }
+            }
+        }
+    }
+
+    report.overlaps = pairs
+        .into_iter()
+        .map(|((first, second), (voxel, samples))| Overlap {
+            first,
+            second,
+            voxel,
+            volume: samples as f32 / (SAMPLES * SAMPLES * SAMPLES) as f32,
+        })
+        .collect();
+
+    report.overlaps.sort_by_key(|overlap| (overlap.first, overlap.second));
+
+    report.gaps = (0..expected.len())
+        .filter(|&index| expected.cells().is_filled(index) && first[index] == 0)
+        .map(|index| expected.position(index))
+        .collect();
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fill::fill_bricks, progress::Hooks};
+
+    fn filled(size: Pos) -> VoxelGrid {
+        let mut grid = VoxelGrid::new(size);
+        for index in 0..grid.len() {
+            grid.cells_mut().set(index, Some(1));
+        }
+        grid
+    }
+
+    fn bricks_for(grid: &VoxelGrid, config: &RampifierConfig) -> Vec<Brick> {
+        fill_bricks(&mut grid.clone(), config, &mut Hooks::new())
+    }
+
+    #[test]
+    fn filled_grid_is_clean() {
+        let config = RampifierConfig::default();
+        let grid = filled((3, 2, 2));
+
+        assert!(validate(&bricks_for(&grid, &config), &config, &grid).is_clean());
+    }
+
+    #[test]
+    fn finds_a_doubled_brick() {
+        let config = RampifierConfig::default();
+        let grid = filled((1, 1, 1));
+
+        let mut bricks = bricks_for(&grid, &config);
+        bricks.push(bricks[0].clone());
+
+        let report = validate(&bricks, &config, &grid);
+        assert_eq!(report.overlaps, [Overlap { first: 0, second: 1, voxel: (0, 0, 0), volume: 1.0 }]);
+        assert!(report.gaps.is_empty());
+    }
+
+    #[test]
+    fn finds_gaps_and_bricks_outside() {
+        let config = RampifierConfig::default();
+        let (small, large) = (filled((1, 1, 1)), filled((2, 1, 1)));
+
+        let report = validate(&bricks_for(&small, &config), &config, &large);
+        assert_eq!(report.gaps, [(1, 0, 0)]);
+        assert!(report.out_of_grid.is_empty());
+
+        let report = validate(&bricks_for(&large, &config), &config, &small);
+        assert_eq!(report.out_of_grid, [0]);
+        assert!(report.gaps.is_empty());
+    }
+
+    #[test]
+    fn skips_other_assets() {
+        let config = RampifierConfig::default();
+        let grid = filled((1, 1, 1));
+
+        let mut bricks = bricks_for(&grid, &config);
+        let mut other = bricks[0].clone();
+        other.asset_name_index = 7;
+        bricks.push(other);
+
+        let report = validate(&bricks, &config, &grid);
+        assert_eq!(report.skipped, [1]);
+        assert!(report.is_clean());
+    }
+}
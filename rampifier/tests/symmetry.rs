@@ -0,0 +1,78 @@
+use brickadia::save::Rotation;
+use rampifier::{
+    grid::VoxelGrid,
+    RampDesc,
+    Rampifier,
+    RampifierConfig,
+    ScanMode,
+    Symmetry,
+    TieBreak,
+    VoxVector,
+};
+
+// A hill peaking in the middle of the grid, symmetric along both X and Y.
+fn hill(size: (usize, usize, usize)) -> VoxelGrid {
+    let mut grid = VoxelGrid::new(size);
+
+    for x in 0..size.0 {
+        for y in 0..size.1 {
+            // Distance from the middle, doubled to stay whole.
+            let dx = (2 * x as isize - (size.0 as isize - 1)).unsigned_abs();
+            let dy = (2 * y as isize - (size.1 as isize - 1)).unsigned_abs();
+            let height = size.2.saturating_sub((dx + dy) / 4).max(1);
+
+            for z in 0..height {
+                grid.set((x, y, z), Some(0));
+            }
+        }
+    }
+
+    grid
+}
+
+// Position and rotation of the ramp mirrored across the plane halfway along X.
+fn mirror_x(desc: &RampDesc, width: usize) -> (VoxVector, Rotation) {
+    let VoxVector(x, y, z) = desc.position;
+
+    let rotation = match desc.rotation {
+        Rotation::Deg0 => Rotation::Deg180,
+        Rotation::Deg180 => Rotation::Deg0,
+        ref rotation => rotation.clone(),
+    };
+
+    (VoxVector(width as isize - 1 - x, y, z), rotation)
+}
+
+#[test]
+fn every_ramp_has_its_mirror() {
+    let config = RampifierConfig {
+        symmetry: Symmetry::MirrorX,
+        scan_mode: ScanMode::Symmetric,
+        tie_break: TieBreak::Neutral,
+        ..RampifierConfig::default()
+    };
+
+    for size in [(10, 10, 6), (11, 7, 5)] {
+        let mut rampifier = Rampifier::new(hill(size), config.clone());
+
+        for is_floor in [true, false] {
+            let analysis = rampifier.analyze(is_floor);
+
+            for desc in &analysis.ramps {
+                let (position, rotation) = mirror_x(desc, size.0);
+
+                assert!(
+                    analysis.ramps.iter().any(|other| {
+                        other.position == position &&
+                            other.rotation == rotation &&
+                            (other.run, other.rise, other.is_floor) == (desc.run, desc.rise, desc.is_floor)
+                    }),
+                    "{:?} grid: ramp at {:?} facing {:?} has no mirror",
+                    size,
+                    desc.position,
+                    desc.rotation,
+                );
+            }
+        }
+    }
+}
@@ -1,13 +1,11 @@
 mod consts;
 use consts::DEFAULT_PALETTE;
 
-use std::{env, fs, fs::File};
-use noise::{RidgedMulti, Seedable};
-use rampifier::{RampifierConfig, Rampifier};
+use std::{env, fs::File};
+use rampifier::{RampifierConfig, Rampifier, color::closest_color_index, components, fill, hollow::{self, HollowMode}, grid::VoxelGrid, progress::Hooks, validate};
 use brickadia::{
     save::*,
     write::SaveWriter,
-    read::SaveReader,
 };
 
 fn main() {
@@ -23,22 +21,30 @@ fn main() {
     const DEFAULT_CHUNK_Z_SIZE: usize = 64 * 2;
 
     // Length of grid vector.
-    const DEFAULT_LEN_X: usize = DEFAULT_CHUNK_X_SIZE * 1;
-    const DEFAULT_LEN_Y: usize = DEFAULT_CHUNK_Y_SIZE * 1;
-    const DEFAULT_LEN_Z: usize = DEFAULT_CHUNK_Z_SIZE * 1;
+    const DEFAULT_LEN_X: usize = DEFAULT_CHUNK_X_SIZE;
+    const DEFAULT_LEN_Y: usize = DEFAULT_CHUNK_Y_SIZE;
+    const DEFAULT_LEN_Z: usize = DEFAULT_CHUNK_Z_SIZE;
 
     // Default save path.
     const DEFAULT_SAVE_PATH: &str = "./out.brs";
 
     let mut save_location = DEFAULT_SAVE_PATH;
 
-    if args.len() > 1
+    if let Some(path) = args.iter().skip(1).find(|arg| !arg.starts_with("--"))
     {
-        save_location = &args[1];
+        save_location = path;
     }
 
-    // This uses u8::MAX to identify an empty voxel. Should we use Option<u8> instead?
-    let mut grid: Vec<Option<u8>> = vec![None; DEFAULT_LEN_X * DEFAULT_LEN_Y * DEFAULT_LEN_Z];
+    // Check the generated bricks for overlaps and gaps before writing the save.
+    let validating = args.iter().any(|arg| arg == "--validate");
+
+    // Remove islands the noise leaves floating above the terrain.
+    let removing_floating = args.iter().any(|arg| arg == "--remove-floating");
+
+    // Merge the buried inside of the terrain into one color before filling.
+    let hollowing = args.iter().any(|arg| arg == "--hollow");
+
+    let mut grid = VoxelGrid::new((DEFAULT_LEN_X, DEFAULT_LEN_Y, DEFAULT_LEN_Z));
 
     let public = User {
         name: "Terrain".into(),
@@ -72,52 +78,19 @@ fn main() {
     //                  PASS 1: GENERATE NOISE                 //
     /////////////////////////////////////////////////////////////
 
-    use noise::{NoiseFn, OpenSimplex, Perlin};
-
-    let simplex = Perlin::new();
+    use noise::{NoiseFn, OpenSimplex};
 
-    let ridged = RidgedMulti::new();
+    let simplex = OpenSimplex::new();
 
     println!("Generating voxel data...");
 
     let now = Instant::now();
 
-    let get_index = |pos: (usize, usize, usize)| -> usize {
-        pos.0 + pos.1 * DEFAULT_LEN_X + pos.2  * DEFAULT_LEN_X * DEFAULT_LEN_Y
-    };
-
-    let color_dist = |color1: &Color, color2: &Color| -> f32 {
-        let r = color1.r as f32 - color2.r as f32;
-        let g = color1.g as f32 - color2.g as f32;
-        let b = color1.b as f32 - color2.b as f32;
-
-        ((r * r) + (g * g) + (b * b)).sqrt()
-    };
-
-    let closest_color_index = |color: &Color, colors: &Vec<brickadia::save::Color>| -> u32 {
-        let mut closest_index = 0u32;
-        let mut closest_dist = f32::MAX;
-
-        for i in 0..colors.len() {
-            let found_color = &colors[i];
-            let found_dist = color_dist(&color, &found_color);
-
-            if closest_dist > found_dist {
-                closest_index = i as u32;
-                closest_dist = found_dist;
-
-            }
-        }
-        closest_index
-    };
-
     for z in 0..DEFAULT_LEN_Z {
         for y in 0..DEFAULT_LEN_Y {
             for x in 0..DEFAULT_LEN_X {
                 // populate da grid
 
-                let z_influence = (-1.0 + z as f64 * 0.015).clamp(0.0, 1.0) * 1.0;
-
                 let scale = 0.1;
 
                 let val = simplex.get([
@@ -155,54 +128,19 @@ fn main() {
 
                 let color = closest_color_index(&sample_color, &save.header2.colors) as u8;
 
-                grid[get_index((x, y, z))] = if val >= 0.5 { Some(color) } else { None };
+                grid.set((x, y, z), if val >= 0.5 { Some(color) } else { None });
             }
         }
     }
 
-    println!(" - Done in {}s\n", now.elapsed().as_millis() as f64 / 1000.0);
+    // Noise leaves islands floating above the terrain, they'd come out as loose bricks.
+    if removing_floating {
+        let removed = components::remove_floating(&mut grid);
 
-    let box_remove = |g: &mut Vec<Option<u8>>, pos: &(usize, usize, usize), size: &(usize, usize, usize)| {
-        let &(x, y, z) = pos;
-        let &(w, l, h) = size;
-
-        for i in 0..w {
-            for j in 0..l {
-                for k in 0..h {
-                    let p = (x + i, y + j, z + k);
-
-                    g[get_index((p.0, p.1, p.2))] = None;
-                }
-            }
-        }
-    };
-
-    let can_box = |g: &Vec<Option<u8>>, value: u8, pos: &(usize, usize, usize), size: &(usize, usize, usize)| -> bool {
-        let &(w, l, h) = size;
-
-        if pos.0 + w > DEFAULT_LEN_X {
-            return false;
-        }
-        if pos.1 + l > DEFAULT_LEN_Y {
-            return false;
-        }
-        if pos.2 + h > DEFAULT_LEN_Z {
-            return false;
-        }
-
-        for i in 0..w {
-            for j in 0..l {
-                for k in 0..h {
-                    let pos = (pos.0 + i, pos.1 + j, pos.2 + k);
-                    if g[get_index((pos.0, pos.1, pos.2))] != Some(value) {
-                        return false;
-                    }
-                }
-            }
-        }
+        println!(" - Removed {} floating voxels", removed);
+    }
 
-        return true;
-    };
+    println!(" - Done in {}s\n", now.elapsed().as_millis() as f64 / 1000.0);
 
 
     /////////////////////////////////////////////////////////////
@@ -213,13 +151,15 @@ fn main() {
 
     let vox_count = grid.len();
 
+    // Voxels the bricks should cover, hollowing below doesn't remove any.
+    let expected = validating.then(|| grid.clone());
+
     let mut rampifier = Rampifier::new(
-        (DEFAULT_LEN_X, DEFAULT_LEN_Y, DEFAULT_LEN_Z),
         grid,
         RampifierConfig::default()
     );
 
-    use std::time::{Duration, Instant};
+    use std::time::Instant;
     let now = Instant::now();
 
     // Generate ramps for floor and ceiling.
@@ -246,63 +186,31 @@ fn main() {
     //         PASS 3: GENERATE OPTIMIZED BRICK FILL           //
     /////////////////////////////////////////////////////////////
 
-    println!("Filling Gaps...");
-
-    for z in 0..DEFAULT_LEN_Z {
-        for y in 0..DEFAULT_LEN_Y {
-            for x in 0..DEFAULT_LEN_X {
-                let mut brick = Brick::default();
-
-                if let Some(val) = grid[get_index((x, y, z))] {
-                    let mut w = 1;
-                    let mut l = 1;
-                    let mut h = 1;
-
-                    // todo: this can be done way better, but this is a shitty quick way to optimize bricks
-                    while can_box(&grid, val, &(x, y, z), &(w, l, h)) && h <= 64 {
-                        h += 1;
-                    }
-
-                    h -= 1;
-
-                    if h > 0 {
-                        while can_box(&grid, val, &(x, y, z), &(w, l, h)) && w <= 64 {
-                            w += 1;
-                        }
+    // Most of the terrain is buried, one color inside lets it be filled with far fewer bricks.
+    if hollowing {
+        let hidden = hollow::hollow(&mut grid, 1, HollowMode::MergeAcrossColors);
 
-                        w -= 1;
-
-                        if w > 0 {
-                            while can_box(&grid, val, &(x, y, z), &(w, l, h)) && l <= 64 {
-                                l += 1;
-                            }
-
-                            l -= 1;
+        println!(" - Merged {} hidden voxels\n", hidden);
+    }
 
-                            if l > 0 {
-                                box_remove(&mut grid, &(x, y, z), &(w, l, h));
+    println!("Filling Gaps...");
 
-                                let size = (w as u32 * 5, l as u32 * 5, h as u32 * 2);
-                                {
-                                    let (x, y, z) = (x as i32 * 10, y as i32 * 10, z as i32 * 4);
+    let bricks = &mut fill::fill_bricks(&mut grid, &RampifierConfig::default(), &mut Hooks::new());
+    save.bricks.append(bricks);
 
-                                    brick.position = (x + size.0 as i32, y + size.1 as i32, z + size.2 as i32);
-                                    brick.size = Size::Procedural(size.0, size.1, size.2);
-                                }
+    println!(" - Gaps filled.");
 
-                                brick.color = BrickColor::Index(val as u32);
+    if let Some(expected) = &expected {
+        let report = validate::validate(&save.bricks, &RampifierConfig::default(), expected);
 
-                                save.bricks.push(brick);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        println!(
+            " - Validated: {} overlaps, {} gaps, {} bricks outside the grid\n",
+            report.overlaps.len(),
+            report.gaps.len(),
+            report.out_of_grid.len(),
+        );
     }
 
-    println!(" - Gaps filled.");
-
     // write out the save
     let file = File::create(save_location);
 
@@ -315,7 +223,7 @@ fn main() {
             println!("Save written to {}", save_location);
         },
         Err(error) => {
-            println!("Could not write to {}, {}", save_location, error.to_string())
+            println!("Could not write to {}, {}", save_location, error)
         }
     }
 }